@@ -3,14 +3,32 @@
 // SPDX-License-Identifier: GPL-3.0-only
 
 use std::{
-    io::Write,
+    collections::{BTreeMap, HashSet},
     path::{Path, PathBuf},
-    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 use maddi_xml as xml;
 
-use crate::ResultExt;
+use crate::{
+    plan::{Action, Remote},
+    query::{Field, Searchable},
+    search::xml_unescape,
+    ResultExt,
+};
+
+/// What `git init` puts in a repository's description.
+const DEFAULT_DESCRIPTION: &str = "Unnamed repository; edit this file 'description' to name the repository.";
+
+/// The directory inside the store that pruned repositories
+/// are moved into.
+const TRASH: &str = ".trash";
+
+/// The prefix of the remotes git-manager pushes mirrors to.
+pub const MIRROR_REMOTE: &str = "git-manager-mirror-";
+
+/// The remote git-manager pulls upstream changes from.
+pub const UPSTREAM_REMOTE: &str = "git-manager-upstream";
 
 #[derive(Debug)]
 struct Symlink {
@@ -21,23 +39,74 @@ impl<'a, 'b> xml::FromElement<'a, 'b> for Symlink {
     fn from_element(
         element: &'b xml::Element<'a>,
     ) -> xml::Result<'a, Self> {
+        use std::path::Component;
         use xml::Content;
-        match element.contents.as_slice() {
-            [Content::Text(path)] => Ok(Self {
-                path: PathBuf::from(path),
-            }),
-            _ => Err(element
-                .position
-                .error("provide a path to symlink".into())),
+        let [Content::Text(path)] =
+            element.contents.as_slice()
+        else {
+            return Err(element.position.error(
+                "provide a path to symlink".into(),
+            ));
+        };
+        // Keep symlinks inside the symlinks directory
+        let path = PathBuf::from(xml_unescape(path));
+        let escapes = path.components().any(|c| {
+            !matches!(
+                c,
+                Component::Normal(_) | Component::CurDir
+            )
+        });
+        if escapes {
+            return Err(element.position.error(
+                "symlink must be a relative path without '..'"
+                    .into(),
+            ));
         }
+        Ok(Self { path })
     }
 }
 
-#[derive(Debug)]
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord,
+)]
 pub enum HookKind {
     PreReceive,
     Update,
     PostReceive,
+    PostUpdate,
+    PushToCheckout,
+    ProcReceive,
+    ReferenceTransaction,
+    PreAutoGc,
+}
+
+impl HookKind {
+    /// Every server-side hook git-manager manages.
+    pub const ALL: [HookKind; 8] = [
+        HookKind::PreReceive,
+        HookKind::Update,
+        HookKind::PostReceive,
+        HookKind::PostUpdate,
+        HookKind::PushToCheckout,
+        HookKind::ProcReceive,
+        HookKind::ReferenceTransaction,
+        HookKind::PreAutoGc,
+    ];
+    /// The name git gives the hook's file.
+    pub fn name(self) -> &'static str {
+        match self {
+            HookKind::PreReceive => "pre-receive",
+            HookKind::Update => "update",
+            HookKind::PostReceive => "post-receive",
+            HookKind::PostUpdate => "post-update",
+            HookKind::PushToCheckout => "push-to-checkout",
+            HookKind::ProcReceive => "proc-receive",
+            HookKind::ReferenceTransaction => {
+                "reference-transaction"
+            }
+            HookKind::PreAutoGc => "pre-auto-gc",
+        }
+    }
 }
 
 impl<'a, 'b> xml::FromValue<'a, 'b> for HookKind {
@@ -45,12 +114,19 @@ impl<'a, 'b> xml::FromValue<'a, 'b> for HookKind {
         value: &'b str,
         position: &'b maddi_xml::Position<'a>,
     ) -> xml::Result<'a, Self> {
-        match value {
-            "pre-receive" => Ok(HookKind::PreReceive),
-            "update" => Ok(HookKind::Update),
-            "post-receive" => Ok(HookKind::PostReceive),
-            _ => Err(position.error("expected 'pre-receive', 'update', or 'post-receive'".into()))
-        }
+        HookKind::ALL
+            .into_iter()
+            .find(|kind| kind.name() == value)
+            .ok_or_else(|| {
+                let kinds = HookKind::ALL
+                    .map(|kind| {
+                        format!("'{}'", kind.name())
+                    })
+                    .join(", ");
+                position.error(format!(
+                    "expected one of {kinds}"
+                ))
+            })
     }
 }
 
@@ -58,101 +134,329 @@ impl<'a, 'b> xml::FromValue<'a, 'b> for HookKind {
 pub enum Source {
     Inline(String),
     File(PathBuf),
+    /// A `<hook-template>` filled in with parameters
+    Template {
+        name: String,
+        params: BTreeMap<String, String>,
+    },
 }
 impl<'a, 'b> xml::FromElement<'a, 'b> for Source {
     fn from_element(
         element: &'b xml::Element<'a>,
     ) -> xml::Result<'a, Self> {
-        const ERR: &str =
-            "expected file content or 'src' attribute";
-        let src =
-            element.attribute::<Option<PathBuf>>("src")?;
-        match (src, element.contents.as_slice()) {
-            (Some(path), []) => Ok(Source::File(path)),
-            (None, [xml::Content::Text(source)]) => {
-                Ok(Source::Inline(source.into()))
+        const ERR: &str = "expected file content, 'src' attribute, or 'template' attribute";
+        let src = element
+            .attribute::<Option<Text>>("src")?
+            .map(|src| PathBuf::from(src.0));
+        let template = element
+            .attribute::<Option<Text>>("template")?
+            .map(|template| template.0);
+        match (src, template, element.contents.as_slice()) {
+            (Some(path), None, []) => {
+                Ok(Source::File(path))
+            }
+            (None, None, [xml::Content::Text(source)]) => {
+                Ok(Source::Inline(xml_unescape(source)))
+            }
+            (None, Some(name), contents) => {
+                let mut params = BTreeMap::new();
+                for content in contents {
+                    let xml::Content::Element(param) =
+                        content
+                    else {
+                        return Err(element.position.error(
+                            "expected only 'param' elements"
+                                .into(),
+                        ));
+                    };
+                    let Param { name, value } =
+                        Param::from_element(param)?;
+                    if params.insert(name, value).is_some()
+                    {
+                        return Err(param.position.error(
+                            "duplicate parameter".into(),
+                        ));
+                    }
+                }
+                Ok(Source::Template { name, params })
             }
             _ => Err(element.position.error(ERR.into())),
         }
     }
 }
 
+/// Reads the file a hook's `src` points to.
+pub type ReadFile<'a> =
+    dyn Fn(&Path) -> Result<String, crate::Error> + 'a;
+
+/// Reads a hook's `src` file from disk.
+pub fn read_file(
+    path: &Path,
+) -> Result<String, crate::Error> {
+    Ok(std::fs::read_to_string(path).with(path)?)
+}
+
 impl Source {
-    pub fn value(self) -> std::io::Result<String> {
+    /// The hook's content with all its parameters and
+    /// variables substituted in, using `read` to get the
+    /// contents of `src` files.
+    pub fn value(
+        &self,
+        templates: &Templates,
+        variables: &Variables,
+        read: &ReadFile<'_>,
+    ) -> Result<String, crate::Error> {
+        let unknown =
+            |name: String, template: Option<&str>| {
+                let known = variables
+                    .keys()
+                    .map(|name| format!("{{{{{name}}}}}"))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let unknown = match template {
+                Some(template) => {
+                    format!("'{{{{{name}}}}}' is not a parameter of template '{template}' or a variable")
+                }
+                None => format!("'{{{{{name}}}}}' is not a variable"),
+            };
+                crate::Error::new(
+                "Unknown variable",
+                format!("{unknown}\n\texpected one of {known}"),
+            )
+            };
+        let Self::Template { name, params } = self else {
+            return substitute(&self.text(read)?, |name| {
+                variables.get(name).cloned()
+            })
+            .map_err(|name| unknown(name, None));
+        };
+        let Some(template) = templates.get(name) else {
+            return Err(crate::Error::new(
+                "Unknown template",
+                format!("'{name}' is not defined"),
+            ));
+        };
+        // Parameters can use variables too
+        let mut values = BTreeMap::new();
+        for (param, value) in params {
+            let value = substitute(value, |name| {
+                variables.get(name).cloned()
+            })
+            .map_err(|name| unknown(name, None))?;
+            values.insert(param.as_str(), value);
+        }
+        substitute(&template.text(read)?, |param| {
+            values
+                .get(param)
+                .or_else(|| variables.get(param))
+                .cloned()
+        })
+        .map_err(|param| unknown(param, Some(name)))
+    }
+    /// The source's content before substitution.
+    fn text(
+        &self,
+        read: &ReadFile<'_>,
+    ) -> Result<String, crate::Error> {
         match self {
-            Self::Inline(source) => Ok(source),
-            Self::File(path) => {
-                std::fs::read_to_string(&path)
+            Self::Inline(source) => Ok(source.clone()),
+            Self::File(path) => read(path),
+            Self::Template { name, .. } => {
+                Err(crate::Error::new(
+                    "Nested template",
+                    format!(
+                        "'{name}' can't be used inside a template"
+                    ),
+                ))
             }
         }
     }
 }
 
+/// The values substituted into hooks for `{{name}}`.
+pub type Variables = BTreeMap<&'static str, String>;
+
+/// Replaces every `{{name}}` in `text` using `lookup`,
+/// failing with the first name it can't find.
+fn substitute(
+    text: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<String, String> {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        result.push_str(&rest[..start]);
+        let tail = &rest[start + 2..];
+        let Some(end) = tail.find("}}") else {
+            result.push_str("{{");
+            rest = tail;
+            break;
+        };
+        // Leave anything that isn't a name alone
+        let name = tail[..end].trim();
+        let valid = !name.is_empty()
+            && name.chars().all(|c| {
+                c.is_ascii_alphanumeric()
+                    || c == '-'
+                    || c == '_'
+            });
+        if !valid {
+            result.push_str("{{");
+            rest = tail;
+            continue;
+        }
+        result.push_str(&lookup(name).ok_or(name)?);
+        rest = &tail[end + 2..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// A `<param name="...">` element passed to a template.
+struct Param {
+    name: String,
+    value: String,
+}
+
+impl<'a, 'b> xml::FromElement<'a, 'b> for Param {
+    fn from_element(
+        element: &'b xml::Element<'a>,
+    ) -> xml::Result<'a, Self> {
+        if element.name != "param" {
+            return Err(element
+                .position
+                .error("expected 'param' element".into()));
+        }
+        Ok(Self {
+            name: element.attribute::<Text>("name")?.0,
+            value: xml_unescape(
+                String::from_element(element)?.trim(),
+            ),
+        })
+    }
+}
+
+/// Named hook sources shared between repositories.
+pub type Templates = BTreeMap<String, Source>;
+
+/// A `<hook-template name="...">` element.
+struct Template {
+    name: String,
+    source: Source,
+}
+
+impl<'a, 'b> xml::FromElement<'a, 'b> for Template {
+    fn from_element(
+        element: &'b xml::Element<'a>,
+    ) -> xml::Result<'a, Self> {
+        let source = Source::from_element(element)?;
+        if let Source::Template { .. } = source {
+            return Err(element.position.error(
+                "templates can't use other templates"
+                    .into(),
+            ));
+        }
+        Ok(Self {
+            name: element.attribute::<Text>("name")?.0,
+            source,
+        })
+    }
+}
+
+/// A `<hook kind="...">` element.
+struct Hook {
+    kind: HookKind,
+    source: Source,
+}
+
+impl<'a, 'b> xml::FromElement<'a, 'b> for Hook {
+    fn from_element(
+        element: &'b xml::Element<'a>,
+    ) -> xml::Result<'a, Self> {
+        Ok(Self {
+            kind: element.attribute("kind")?,
+            source: Source::from_element(element)?,
+        })
+    }
+}
+
 #[derive(Debug)]
 pub struct Hooks {
-    pre_receive: Option<Source>,
-    update: Option<Source>,
-    post_receive: Option<Source>,
+    sources: BTreeMap<HookKind, Source>,
 }
 
 impl Hooks {
-    fn update_hook(
-        path: &Path,
-        source: &Option<Source>,
-    ) -> Result<(), crate::error::Error> {
-        // Delete the file
-        if source.is_none() && path.exists() {
-            return std::fs::remove_file(path)
-                .with(path)
-                .map_err(Into::into);
-        }
-        // Create/Update the content of the file
-        if let Some(content) = source
-            .clone()
-            .map(Source::value)
-            .transpose()
-            .with(path)?
-        {
-            // Create/Update
-            let mut file = std::fs::File::options()
-                .create(true)
-                .write(true)
-                .truncate(true)
-                .open(path)
-                .with(path)?;
-            file.write_all(content.as_bytes())
-                .with(path)?;
-            // Make the file executable
-            Command::new("chmod")
-                .arg("+x")
-                .arg(path)
-                .output()
-                .with(path)?;
-        }
-        Ok(())
-    }
-    pub fn update(
+    /// Plans the changes needed to make the hooks in
+    /// `hook_directory` match the config, deleting any
+    /// hooks that aren't configured.
+    pub fn plan(
         &self,
         hook_directory: &Path,
-    ) -> Result<(), crate::error::Error> {
-        // Set up the pre_receive hook
-        let pre_receive_file =
-            hook_directory.join("pre-receive");
-        Self::update_hook(
-            &pre_receive_file,
-            &self.pre_receive,
-        )?;
-        // Set up the update hook
-        let update_file = hook_directory.join("update");
-        Self::update_hook(&update_file, &self.update)?;
-        // Set up the post_receive hook
-        let post_receive_file =
-            hook_directory.join("post-receive");
-        Self::update_hook(
-            &post_receive_file,
-            &self.post_receive,
-        )?;
-        Ok(())
+        templates: &Templates,
+        variables: &Variables,
+        mirrored: bool,
+        read: &ReadFile<'_>,
+    ) -> Result<Vec<Action>, crate::error::Error> {
+        let mut contents = BTreeMap::new();
+        for (kind, source) in &self.sources {
+            let path = hook_directory.join(kind.name());
+            let content = source
+                .value(templates, variables, read)
+                .map_err(|err| {
+                    err.within(path.display())
+                })?;
+            contents.insert(*kind, content);
+        }
+        // Mirrors are pushed by a generated post-receive
+        // hook that then runs the configured one
+        let configured = if mirrored {
+            let path = hook_directory.join("post-receive");
+            let hook = Source::Inline(
+                include_str!("mirror.sh").into(),
+            )
+            .value(templates, variables, &read_file)
+            .map_err(|err| err.within(path.display()))?;
+            contents.insert(HookKind::PostReceive, hook)
+        } else {
+            None
+        };
+        let mut actions = vec![];
+        for kind in HookKind::ALL {
+            let path = hook_directory.join(kind.name());
+            let content = contents.remove(&kind);
+            actions.extend(Action::write_hook(
+                &path, content,
+            )?);
+        }
+        let path = hook_directory.join("post-receive.user");
+        actions
+            .extend(Action::write_hook(&path, configured)?);
+        Ok(actions)
+    }
+}
+
+impl Hooks {
+    /// Layers `overrides` on top of these hooks.
+    fn merge(&mut self, overrides: &Hooks) {
+        for (kind, source) in &overrides.sources {
+            self.sources.insert(*kind, source.clone());
+        }
+    }
+    /// Finds a template used by a hook that isn't defined.
+    fn unknown_template(
+        &self,
+        templates: &Templates,
+    ) -> Option<&str> {
+        self.sources.values().find_map(
+            |source| match source {
+                Source::Template { name, .. }
+                    if !templates.contains_key(name) =>
+                {
+                    Some(name.as_str())
+                }
+                _ => None,
+            },
+        )
     }
 }
 
@@ -160,16 +464,140 @@ impl<'a, 'b> xml::FromElement<'a, 'b> for Hooks {
     fn from_element(
         element: &'b maddi_xml::Element<'a>,
     ) -> maddi_xml::Result<'a, Self> {
+        let mut sources = BTreeMap::new();
+        // Hooks can be their own element, like <update>
+        for kind in HookKind::ALL {
+            if let Some(source) =
+                element.optional_child(kind.name())?
+            {
+                sources.insert(kind, source);
+            }
+        }
+        // Or a <hook kind="update"> element
+        for hook in
+            element.children::<&xml::Element>("hook")
+        {
+            let hook = hook?;
+            let Hook { kind, source } =
+                Hook::from_element(hook)?;
+            if sources.insert(kind, source).is_some() {
+                return Err(hook.position.error(format!(
+                    "duplicate '{}' hook",
+                    kind.name()
+                )));
+            }
+        }
+        Ok(Self { sources })
+    }
+}
+
+/// A `<mirror url="..." />` or `<upstream url="..." />`
+/// element: a repository that branches are pushed to or
+/// pulled from.
+#[derive(Debug)]
+pub struct Mirror {
+    url: String,
+    branches: Option<Vec<String>>,
+}
+
+impl Mirror {
+    /// The branches to keep in sync, falling back to the
+    /// default branch.
+    pub fn branches(&self, default: &str) -> Vec<String> {
+        self.branches
+            .clone()
+            .unwrap_or_else(|| vec![default.into()])
+    }
+}
+
+impl<'a, 'b> xml::FromElement<'a, 'b> for Mirror {
+    fn from_element(
+        element: &'b xml::Element<'a>,
+    ) -> xml::Result<'a, Self> {
         Ok(Self {
-            pre_receive: element
-                .optional_child("pre-receive")?,
-            update: element.optional_child("update")?,
-            post_receive: element
-                .optional_child("post-receive")?,
+            url: element.attribute::<Text>("url")?.0,
+            branches: element
+                .attribute::<Option<Text>>("branches")?
+                .map(|branches| {
+                    branches
+                        .0
+                        .split_whitespace()
+                        .map(Into::into)
+                        .collect()
+                }),
         })
     }
 }
 
+/// Git config entries, keyed by their name.
+pub type GitConfig = BTreeMap<String, String>;
+
+/// Reads every `<git-config key="...">` child of an element.
+fn git_config<'a>(
+    element: &xml::Element<'a>,
+) -> xml::Result<'a, GitConfig> {
+    let mut entries = GitConfig::new();
+    for entry in
+        element.children::<&xml::Element>("git-config")
+    {
+        let entry = entry?;
+        let key = entry.attribute::<Text>("key")?.0;
+        if !key.contains('.')
+            || key.contains(char::is_whitespace)
+        {
+            return Err(entry.position.error(format!(
+                "'{key}' is not a valid git config key"
+            )));
+        }
+        let value = match entry.contents.as_slice() {
+            [] => String::new(),
+            _ => xml_unescape(
+                <String as xml::FromElement>::from_element(
+                    entry,
+                )?
+                .trim(),
+            ),
+        };
+        if entries.insert(key, value).is_some() {
+            return Err(entry
+                .position
+                .error("duplicate git config key".into()));
+        }
+    }
+    Ok(entries)
+}
+
+/// A 'true' or 'false' value.
+struct Bool(bool);
+
+impl<'a, 'b> xml::FromValue<'a, 'b> for Bool {
+    fn from_value(
+        value: &'b str,
+        position: &'b maddi_xml::Position<'a>,
+    ) -> xml::Result<'a, Self> {
+        match value.trim() {
+            "true" => Ok(Bool(true)),
+            "false" => Ok(Bool(false)),
+            _ => Err(position.error(
+                "expected 'true' or 'false'".into(),
+            )),
+        }
+    }
+}
+
+/// Text with its entities decoded, since maddi_xml leaves
+/// them as they are.
+struct Text(String);
+
+impl<'a, 'b> xml::FromValue<'a, 'b> for Text {
+    fn from_value(
+        value: &'b str,
+        _position: &'b maddi_xml::Position<'a>,
+    ) -> xml::Result<'a, Self> {
+        Ok(Text(xml_unescape(value)))
+    }
+}
+
 struct Tag(String);
 
 impl<'a, 'b> xml::FromElement<'a, 'b> for Tag {
@@ -181,7 +609,7 @@ impl<'a, 'b> xml::FromElement<'a, 'b> for Tag {
                 if tag.contains(char::is_whitespace) {
                     Err(element.position.error("tag must not contain whitespace".into()))
                 } else {
-                    Ok(Tag(tag.into()))
+                    Ok(Tag(xml_unescape(tag)))
                 }
             },
             _ => Err(element.position.error("expected tag to contain text with no whitespace".into())),
@@ -189,90 +617,404 @@ impl<'a, 'b> xml::FromElement<'a, 'b> for Tag {
     }
 }
 
+/// Descriptive details about a repository.
+#[derive(Debug, Default)]
+pub struct Metadata {
+    pub description: Option<String>,
+    pub homepage: Option<String>,
+    pub owner: Option<String>,
+    pub license: Option<String>,
+}
+
+impl Metadata {
+    /// Every field that's set, along with its name.
+    pub fn fields(
+        &self,
+    ) -> impl Iterator<Item = (&'static str, &str)> {
+        [
+            ("description", &self.description),
+            ("homepage", &self.homepage),
+            ("owner", &self.owner),
+            ("license", &self.license),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| {
+            Some((name, value.as_deref()?))
+        })
+    }
+}
+
+impl<'a, 'b> xml::FromElement<'a, 'b> for Metadata {
+    fn from_element(
+        element: &'b xml::Element<'a>,
+    ) -> xml::Result<'a, Self> {
+        let field = |name| {
+            Ok(element
+                .optional_child::<String>(name)?
+                .map(|value| xml_unescape(value.trim())))
+        };
+        Ok(Self {
+            description: field("description")?,
+            homepage: field("homepage")?,
+            owner: field("owner")?,
+            license: field("license")?,
+        })
+    }
+}
+
+/// Settings inherited by every repository with a tag.
+#[derive(Debug)]
+pub struct TagSettings {
+    hooks: Hooks,
+    git_config: GitConfig,
+}
+
+/// A `<tag-config name="...">` element.
+struct TagConfig {
+    name: String,
+    settings: TagSettings,
+}
+
+impl<'a, 'b> xml::FromElement<'a, 'b> for TagConfig {
+    fn from_element(
+        element: &'b xml::Element<'a>,
+    ) -> xml::Result<'a, Self> {
+        let name = element.attribute::<Text>("name")?.0;
+        if name.contains(char::is_whitespace) {
+            return Err(element.position.error(
+                "tag must not contain whitespace".into(),
+            ));
+        }
+        Ok(Self {
+            name,
+            settings: TagSettings {
+                hooks: Hooks::from_element(element)?,
+                git_config: git_config(element)?,
+            },
+        })
+    }
+}
+
 #[derive(Debug)]
 pub struct Repository {
     name: String,
+    formerly: Option<String>,
+    keep_formerly: bool,
     symlinks: Vec<Symlink>,
     tags: Vec<String>,
     hooks: Hooks,
+    mirrors: Vec<Mirror>,
+    upstream: Option<Mirror>,
+    bare: Option<bool>,
+    branch: Option<String>,
+    git_config: GitConfig,
+    metadata: Metadata,
 }
 
 impl Repository {
-    pub fn smartget_filter_map(
-        &self,
-        search: &str,
-        store_dir: &Path,
-    ) -> Option<String> {
-        let matches =
-            search.split_whitespace().all(|term| {
-                self.tags
-                    .iter()
-                    .any(|tag| tag.contains(term))
-            }) || self.name.contains(search);
-        if !matches {
-            return None;
-        }
-        let store = store_dir.display();
-        let name = &self.name;
-        Some(format!("<git><alias>{name}</alias><path>{store}/{name}</path></git>"))
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+    pub fn upstream(&self) -> Option<&Mirror> {
+        self.upstream.as_ref()
+    }
+    /// The repository's default branch.
+    pub fn branch<'a>(
+        &'a self,
+        config: &'a Config,
+    ) -> &'a str {
+        self.branch.as_deref().unwrap_or(&config.branch)
+    }
+    /// Whether the repository is kept without a worktree.
+    pub fn bare(&self, config: &Config) -> bool {
+        self.bare.unwrap_or(config.bare)
+    }
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
     }
     pub fn admin() -> Self {
         Repository {
             name: "admin".into(),
+            formerly: None,
+            keep_formerly: false,
             symlinks: vec![Symlink {
                 path: "admin".into(),
             }],
             tags: vec![],
+            mirrors: vec![],
+            upstream: None,
+            // The admin hooks need config.xml checked out
+            bare: Some(false),
+            branch: None,
+            git_config: GitConfig::new(),
+            metadata: Metadata::default(),
             hooks: Hooks {
-                pre_receive: None,
-                update: None,
-                post_receive: Some(Source::Inline(
-                    include_str!("post-update.sh").into(),
+                sources: BTreeMap::from([
+                    (
+                        HookKind::PreReceive,
+                        Source::Inline(
+                            include_str!("pre-receive.sh")
+                                .into(),
+                        ),
+                    ),
+                    (
+                        HookKind::PostReceive,
+                        Source::Inline(
+                            include_str!("post-update.sh")
+                                .into(),
+                        ),
+                    ),
+                ]),
+            },
+        }
+    }
+    /// Plans the changes needed to make the server match
+    /// this repository's config, reading hooks' `src`
+    /// files with `read`.
+    pub fn plan(
+        &self,
+        config: &Config,
+        read: &ReadFile<'_>,
+    ) -> Result<Vec<Action>, crate::Error> {
+        let mut actions = vec![];
+        // Find where the repository currently is
+        let repository_path = config.store.join(&self.name);
+        let former_path = self
+            .formerly
+            .as_ref()
+            .map(|formerly| config.store.join(formerly));
+        let renamed =
+            former_path.as_ref().filter(|former| {
+                former.is_dir() && !former.is_symlink()
+            });
+        let bare = self.bare(config);
+        let existing = match renamed {
+            Some(former) if repository_path.exists() => {
+                return Err(crate::Error::new(
+                    "Rename conflict",
+                    format!(
+                        "both {} and {} exist",
+                        former.display(),
+                        repository_path.display()
+                    ),
+                ));
+            }
+            // Move the repository to its new name
+            Some(former) => {
+                actions.push(Action::Rename {
+                    from: former.clone(),
+                    to: repository_path.clone(),
+                });
+                former.clone()
+            }
+            None => {
+                // Create the repository
+                if !repository_path.exists() {
+                    actions.push(match &self.upstream {
+                        Some(upstream) => Action::Clone {
+                            path: repository_path.clone(),
+                            url: upstream.url.clone(),
+                            remote: UPSTREAM_REMOTE.into(),
+                            branch: self
+                                .branch(config)
+                                .into(),
+                            bare,
+                        },
+                        None => Action::Init {
+                            path: repository_path.clone(),
+                            branch: self
+                                .branch(config)
+                                .into(),
+                            bare,
+                        },
+                    });
+                }
+                repository_path.clone()
+            }
+        };
+        // Make sure the repository on disk is laid out the
+        // way the config says
+        let has_worktree = existing.join(".git").exists();
+        if existing.exists() && has_worktree == bare {
+            let (problem, fix) = if bare {
+                ("has a worktree", format!("run 'git-manager convert --bare {}'", self.name))
+            } else {
+                (
+                    "is bare",
+                    "set bare=\"true\" on the repository"
+                        .into(),
+                )
+            };
+            return Err(crate::Error::new(
+                "Repository layout mismatch",
+                format!(
+                    "{} {problem}\n\t{fix}",
+                    existing.display()
+                ),
+            ));
+        }
+        let mut repository = vec![];
+        // Keep the repository's git config in sync
+        repository.extend(Action::git_config(
+            &existing,
+            config.git_config(self),
+        )?);
+        // Follow changes to the default branch
+        repository.extend(Action::set_head(
+            &existing,
+            self.branch(config),
+        )?);
+        // Ensure the repositories hooks are correct
+        let git_dir = if bare {
+            existing.clone()
+        } else {
+            existing.join(".git")
+        };
+        repository.extend(config.hooks(self).plan(
+            &git_dir.join("hooks"),
+            &config.templates,
+            &self.variables(config),
+            !self.mirrors.is_empty(),
+            read,
+        )?);
+        // Describe the repository for tools like gitweb,
+        // putting back git's placeholder once it's removed
+        let description = git_dir.join("description");
+        match &self.metadata.description {
+            Some(text) => repository.extend(
+                Action::describe(&description, text),
+            ),
+            None if description.exists() => repository
+                .extend(Action::describe(
+                    &description,
+                    DEFAULT_DESCRIPTION,
                 )),
+            None => {}
+        }
+        // Keep the mirror remotes in sync
+        let mirrors = self
+            .mirrors
+            .iter()
+            .enumerate()
+            .map(|(index, mirror)| {
+                let branches =
+                    mirror.branches(self.branch(config));
+                Remote {
+                    name: format!("{MIRROR_REMOTE}{index}"),
+                    url: mirror.url.clone(),
+                    fetch: vec![],
+                    push: branches
+                        .iter()
+                        .map(|branch| {
+                            format!("+refs/heads/{branch}:refs/heads/{branch}")
+                        })
+                        .collect(),
+                }
+            })
+            .collect();
+        repository.extend(Action::remotes(
+            &existing,
+            MIRROR_REMOTE,
+            mirrors,
+        )?);
+        // Keep the upstream remote in sync
+        let upstream = self.upstream.iter().map(|upstream| {
+            Remote {
+                name: UPSTREAM_REMOTE.into(),
+                url: upstream.url.clone(),
+                fetch: vec![format!(
+                    "+refs/heads/*:refs/remotes/{UPSTREAM_REMOTE}/*"
+                )],
+                push: vec![],
+            }
+        });
+        repository.extend(Action::remotes(
+            &existing,
+            &format!("{UPSTREAM_REMOTE}."),
+            upstream.collect(),
+        )?);
+        actions.extend(repository.into_iter().map(
+            |action| {
+                action.moved(&existing, &repository_path)
             },
+        ));
+        // Leave a symlink behind for the old name
+        if let Some(former) =
+            former_path.filter(|_| self.keep_formerly)
+        {
+            actions.extend(Action::symlink(
+                &former,
+                &repository_path,
+            ));
         }
+        // Create all the symlinks
+        for link in self.symlinks(&config.symlinks) {
+            actions.extend(Action::symlink(
+                &link,
+                &repository_path,
+            ));
+        }
+        Ok(actions)
+    }
+    /// The variables available to this repository's hooks.
+    fn variables(&self, config: &Config) -> Variables {
+        let join = |items: Vec<String>| items.join(" ");
+        let git_manager = std::env::current_exe()
+            .map(|exe| exe.display().to_string())
+            .unwrap_or_else(|_| "git-manager".into());
+        Variables::from([
+            ("name", self.name.clone()),
+            ("store", config.store.display().to_string()),
+            (
+                "path",
+                config
+                    .store
+                    .join(&self.name)
+                    .display()
+                    .to_string(),
+            ),
+            (
+                "symlinks",
+                join(
+                    self.symlinks(&config.symlinks)
+                        .map(|link| {
+                            link.display().to_string()
+                        })
+                        .collect(),
+                ),
+            ),
+            (
+                "git-dir",
+                self.git_dir(config).display().to_string(),
+            ),
+            ("branch", self.branch(config).into()),
+            ("tags", join(self.tags.clone())),
+            ("git-manager", git_manager),
+        ])
     }
+    /// The repository's git directory.
+    fn git_dir(&self, config: &Config) -> PathBuf {
+        let path = config.store.join(&self.name);
+        match self.bare(config) {
+            true => path,
+            false => path.join(".git"),
+        }
+    }
+    /// Makes the server match this repository's config,
+    /// returning the path to the repository.
     pub fn switch(
         &self,
-        branch: &str,
-        symlinks_dir: &Path,
-        store_dir: &Path,
+        config: &Config,
     ) -> std::result::Result<PathBuf, crate::Error> {
-        // Check if the repository already exists
-        let repository_path = store_dir.join(&self.name);
-        if !repository_path.exists() {
-            // Create the repository
-            let mut command = Command::new("git");
-            command
-                .arg("--git-dir=.git")
-                .arg("init")
-                .arg("-b")
-                .arg(branch)
-                .arg(&repository_path);
-            command.output().with(command)?;
-        }
-        // Configure the repository to accept pushes
-        let mut cmd = Command::new("git");
-        cmd.args([
-            "--git-dir=.git",
-            "config",
-            "--local",
-            "receive.denyCurrentBranch",
-            "ignore",
-        ])
-        .current_dir(&repository_path);
-        cmd.output().with(cmd)?;
-        // Ensure the repositories hooks are correct
-        self.hooks
-            .update(&repository_path.join(".git/hooks"))?;
-        // Create all the symlinks
-        self.build_symlinks(
-            &repository_path,
-            symlinks_dir,
-        )?;
-        Ok(repository_path)
+        for action in self.plan(config, &read_file)? {
+            action.apply()?;
+        }
+        Ok(config.store.join(&self.name))
     }
-    fn symlinks<'a, 'b>(
+    pub fn symlinks<'a, 'b>(
         &'a self,
         symlinks_dir: &'b Path,
     ) -> impl Iterator<Item = PathBuf> + use<'a, 'b> {
@@ -280,29 +1022,32 @@ impl Repository {
             .iter()
             .map(|s| symlinks_dir.join(&s.path))
     }
-    fn build_symlinks(
-        &self,
-        repository_path: &Path,
-        symlinks_dir: &Path,
-    ) -> Result<(), crate::error::Error> {
-        // Create all the symlinks
-        for target in self.symlinks(symlinks_dir) {
-            // Ensure the parent directory exists
-            let parent = target.parent().unwrap();
-            std::fs::create_dir_all(parent).with(parent)?;
-            // If the symlink exists, delete it.
-            if target.exists() {
-                std::fs::remove_file(&target)
-                    .with(target.as_path())?;
+}
+
+impl Searchable for Repository {
+    fn values(&self, field: Field) -> Vec<String> {
+        let metadata = |value: &Option<String>| {
+            value.iter().cloned().collect()
+        };
+        match field {
+            Field::Name => vec![self.name.clone()],
+            Field::Tag => self.tags.clone(),
+            Field::Symlink => self
+                .symlinks
+                .iter()
+                .map(|link| link.path.display().to_string())
+                .collect(),
+            Field::Description => {
+                metadata(&self.metadata.description)
+            }
+            Field::Homepage => {
+                metadata(&self.metadata.homepage)
+            }
+            Field::Owner => metadata(&self.metadata.owner),
+            Field::License => {
+                metadata(&self.metadata.license)
             }
-            // Create the symlink
-            std::os::unix::fs::symlink(
-                repository_path,
-                &target,
-            )
-            .with([repository_path, target.as_path()])?;
         }
-        Ok(())
     }
 }
 
@@ -310,8 +1055,53 @@ impl<'a, 'b> xml::FromElement<'a, 'b> for Repository {
     fn from_element(
         element: &'b xml::Element<'a>,
     ) -> xml::Result<'a, Self> {
+        // Keep repositories directly inside the store
+        let valid = |name: &str| {
+            !name.is_empty()
+                && !name.starts_with('.')
+                && !name.contains('/')
+        };
+        let name = element.attribute::<Text>("name")?.0;
+        let formerly = element
+            .attribute::<Option<Text>>("formerly")?
+            .map(|formerly| formerly.0);
+        let names =
+            std::iter::once(&name).chain(formerly.as_ref());
+        for name in names {
+            if !valid(name) {
+                return Err(element.position.error(format!(
+                    "'{name}' is not a valid repository name"
+                )));
+            }
+        }
+        let keep_formerly = element
+            .attribute::<Option<Bool>>("keep-formerly")?
+            .is_some_and(|keep| keep.0);
+        if keep_formerly && formerly.is_none() {
+            return Err(element.position.error(
+                "'keep-formerly' requires a 'formerly' name"
+                    .into(),
+            ));
+        }
+        let bare = element
+            .attribute::<Option<Bool>>("bare")?
+            .map(|bare| bare.0);
+        // The admin hooks need config.xml checked out, so
+        // it keeps its worktree whatever `<bare>` says
+        let bare = match (name.as_str(), bare) {
+            ("admin", Some(true)) => {
+                return Err(element.position.error(
+                    "the admin repository can't be bare"
+                        .into(),
+                ))
+            }
+            ("admin", _) => Some(false),
+            (_, bare) => bare,
+        };
         Ok(Self {
-            name: element.attribute::<&str>("name")?.into(),
+            name,
+            formerly,
+            keep_formerly,
             symlinks: element
                 .children::<Symlink>("symlink")
                 .collect::<xml::Result<_>>()?,
@@ -320,29 +1110,361 @@ impl<'a, 'b> xml::FromElement<'a, 'b> for Repository {
                 .map(|tag| tag.map(|tag| tag.0))
                 .collect::<Result<_, _>>()?,
             hooks: Hooks::from_element(element)?,
+            mirrors: element
+                .children("mirror")
+                .collect::<xml::Result<_>>()?,
+            upstream: element.optional_child("upstream")?,
+            bare,
+            branch: element
+                .attribute::<Option<Text>>("branch")?
+                .map(|branch| branch.0),
+            git_config: git_config(element)?,
+            metadata: Metadata::from_element(element)?,
         })
     }
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Prune {
+    /// Leave removed repositories and symlinks in place
+    #[default]
+    Never,
+    /// Prune removed repositories and symlinks on switch
+    Always,
+}
+
+impl<'a, 'b> xml::FromValue<'a, 'b> for Prune {
+    fn from_value(
+        value: &'b str,
+        position: &'b maddi_xml::Position<'a>,
+    ) -> xml::Result<'a, Self> {
+        match value.trim() {
+            "never" => Ok(Prune::Never),
+            "always" => Ok(Prune::Always),
+            _ => Err(position.error(
+                "expected 'never' or 'always'".into(),
+            )),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Config {
     pub branch: String,
     pub store: PathBuf,
     pub symlinks: PathBuf,
+    pub prune: Prune,
+    pub bare: bool,
+    pub git_config: GitConfig,
+    pub templates: Templates,
+    pub tags: BTreeMap<String, TagSettings>,
     pub repositories: Vec<Repository>,
 }
 
+impl Config {
+    /// Plans the changes needed to make the server match
+    /// the config, reading hooks' `src` files with `read`
+    /// and including `prune`'s actions if asked to.
+    pub fn plan(
+        &self,
+        prune: bool,
+        read: &ReadFile<'_>,
+    ) -> Result<Vec<Action>, crate::Error> {
+        let mut actions = vec![];
+        for repository in &self.repositories {
+            actions.extend(repository.plan(self, read)?);
+        }
+        if prune {
+            actions.extend(self.prune()?);
+        }
+        Ok(actions)
+    }
+    /// The hooks a repository ends up with once everything
+    /// inherited from its tags is included.
+    fn hooks(&self, repository: &Repository) -> Hooks {
+        let mut hooks = Hooks {
+            sources: BTreeMap::new(),
+        };
+        let inherited = repository
+            .tags
+            .iter()
+            .filter_map(|tag| self.tags.get(tag));
+        for tag in inherited {
+            hooks.merge(&tag.hooks);
+        }
+        hooks.merge(&repository.hooks);
+        hooks
+    }
+    /// The git config a repository ends up with, layering
+    /// the config-wide entries, then those inherited from
+    /// its tags, then its own.
+    fn git_config(
+        &self,
+        repository: &Repository,
+    ) -> GitConfig {
+        let mut entries = GitConfig::new();
+        // Let pushes update the checked out branch
+        if !repository.bare(self) {
+            entries.insert(
+                "receive.denyCurrentBranch".into(),
+                "ignore".into(),
+            );
+        }
+        entries.extend(self.git_config.clone());
+        let inherited = repository
+            .tags
+            .iter()
+            .filter_map(|tag| self.tags.get(tag));
+        for tag in inherited {
+            entries.extend(tag.git_config.clone());
+        }
+        entries.extend(repository.git_config.clone());
+        entries
+    }
+    /// Plans moving repositories that are no longer in the
+    /// config into the store's trash and removing any
+    /// symlinks into the store that are no longer in the
+    /// config.
+    pub fn prune(
+        &self,
+    ) -> Result<Vec<Action>, crate::error::Error> {
+        let mut pruned = vec![];
+        // Nothing has been created yet
+        let store = self.store.as_path();
+        if !store.is_dir() {
+            return Ok(pruned);
+        }
+        // The admin repository is running this, so keep it
+        // even if it isn't listed
+        let admin = Repository::admin();
+        let listed = self
+            .repositories
+            .iter()
+            .any(|repo| repo.name == admin.name);
+        let repositories = self
+            .repositories
+            .iter()
+            .chain((!listed).then_some(&admin))
+            .collect::<Vec<_>>();
+        // Trash any repositories not in the config
+        for entry in std::fs::read_dir(store).with(store)? {
+            let entry = entry.with(store)?;
+            let name = entry.file_name();
+            // A former name is either being renamed away
+            // from or kept as a symlink, so leave it to
+            // the repository's plan
+            let known = name == TRASH
+                || repositories.iter().any(|repo| {
+                    name == *repo.name
+                        || repo
+                            .formerly
+                            .as_ref()
+                            .is_some_and(|f| name == **f)
+                });
+            if known {
+                continue;
+            }
+            let from = entry.path();
+            let to = self.trash_path(&name);
+            pruned.push(Action::Trash { from, to });
+        }
+        // Remove symlinks that are not in the config
+        let expected = repositories
+            .iter()
+            .flat_map(|repo| repo.symlinks(&self.symlinks))
+            .collect::<HashSet<_>>();
+        let mut found = vec![];
+        find_symlinks(&self.symlinks, &mut found)?;
+        for symlink in found {
+            if expected.contains(&symlink) {
+                continue;
+            }
+            // Leave symlinks we didn't create alone
+            let target = std::fs::read_link(&symlink)
+                .with(symlink.as_path())?;
+            let dangling = !symlink.exists();
+            if !dangling && !target.starts_with(store) {
+                continue;
+            }
+            pruned.push(Action::RemoveSymlink {
+                link: symlink,
+                target,
+            });
+        }
+        Ok(pruned)
+    }
+    /// Plans turning the repository in the store called
+    /// `name` into a bare repository.
+    pub fn convert_to_bare(
+        &self,
+        name: &str,
+    ) -> Result<Action, crate::error::Error> {
+        if name == "admin" {
+            return Err(crate::Error::new(
+                "Can't convert",
+                "the admin repository needs its worktree",
+            ));
+        }
+        let path = self.store.join(name);
+        if !path.join(".git").is_dir() {
+            return Err(crate::Error::new(
+                "Nothing to convert",
+                format!(
+                    "{} is not a repository with a worktree",
+                    path.display()
+                ),
+            ));
+        }
+        Ok(Action::ConvertToBare {
+            worktree: self.trash_path(name.as_ref()),
+            path,
+        })
+    }
+    /// Finds a free path in the trash for a repository.
+    fn trash_path(
+        &self,
+        name: &std::ffi::OsStr,
+    ) -> PathBuf {
+        let trash = self.store.join(TRASH);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|now| now.as_secs())
+            .unwrap_or_default();
+        let mut path = trash.join(name);
+        let mut attempt = 0;
+        while path.symlink_metadata().is_ok() {
+            attempt += 1;
+            let mut file = name.to_os_string();
+            file.push(format!(".{now}.{attempt}"));
+            path = trash.join(file);
+        }
+        path
+    }
+}
+
+/// Recursively collects every symlink under `dir` without
+/// following them.
+fn find_symlinks(
+    dir: &Path,
+    found: &mut Vec<PathBuf>,
+) -> Result<(), crate::error::Error> {
+    if !dir.is_dir() {
+        return Ok(());
+    }
+    for entry in std::fs::read_dir(dir).with(dir)? {
+        let entry = entry.with(dir)?;
+        let path = entry.path();
+        let kind =
+            entry.file_type().with(path.as_path())?;
+        if kind.is_symlink() {
+            found.push(path);
+        } else if kind.is_dir() {
+            find_symlinks(&path, found)?;
+        }
+    }
+    Ok(())
+}
+
 impl<'a, 'b> xml::FromElement<'a, 'b> for Config {
     fn from_element(
         element: &'b xml::Element<'a>,
     ) -> xml::Result<'a, Self> {
+        // Get all the hook templates
+        let mut templates = Templates::new();
+        for template in element
+            .children::<&xml::Element>("hook-template")
+        {
+            let template = template?;
+            let Template { name, source } =
+                Template::from_element(template)?;
+            if templates.insert(name, source).is_some() {
+                return Err(template.position.error(
+                    "duplicate template name".into(),
+                ));
+            }
+        }
+        // Get the settings shared by tagged repositories
+        let mut tags = BTreeMap::new();
+        for tag in
+            element.children::<&xml::Element>("tag-config")
+        {
+            let tag = tag?;
+            let TagConfig { name, settings } =
+                TagConfig::from_element(tag)?;
+            let unknown =
+                settings.hooks.unknown_template(&templates);
+            if let Some(name) = unknown {
+                return Err(tag.position.error(format!(
+                    "unknown hook template '{name}'"
+                )));
+            }
+            if tags.insert(name, settings).is_some() {
+                return Err(tag.position.error(
+                    "duplicate tag-config name".into(),
+                ));
+            }
+        }
+        // Get all the repositories
+        let mut repositories = vec![];
+        let mut names = HashSet::new();
+        let mut symlinks = HashSet::new();
+        for repo in
+            element.children::<&xml::Element>("repo")
+        {
+            let repo = repo?;
+            let repository =
+                Repository::from_element(repo)?;
+            // Ensure repositories don't overlap
+            if !names.insert(repository.name.clone()) {
+                return Err(repo.position.error(format!(
+                    "duplicate repository '{}'",
+                    repository.name
+                )));
+            }
+            let former =
+                repository.formerly.iter().find(|former| {
+                    !names.insert((*former).clone())
+                });
+            if let Some(former) = former {
+                return Err(repo.position.error(format!(
+                    "repository '{former}' is used more than once"
+                )));
+            }
+            let unknown = repository
+                .hooks
+                .unknown_template(&templates);
+            if let Some(name) = unknown {
+                return Err(repo.position.error(format!(
+                    "unknown hook template '{name}'"
+                )));
+            }
+            for symlink in &repository.symlinks {
+                if !symlinks.insert(symlink.path.clone()) {
+                    return Err(repo.position.error(format!(
+                        "symlink '{}' is used more than once",
+                        symlink.path.display()
+                    )));
+                }
+            }
+            repositories.push(repository);
+        }
         Ok(Self {
-            branch: element.child("branch")?,
-            store: element.child("store")?,
-            symlinks: element.child("symlinks")?,
-            repositories: element
-                .children::<Repository>("repo")
-                .collect::<xml::Result<_>>()?,
+            branch: element.child::<Text>("branch")?.0,
+            store: element.child::<Text>("store")?.0.into(),
+            symlinks: element
+                .child::<Text>("symlinks")?
+                .0
+                .into(),
+            prune: element
+                .optional_child("prune")?
+                .unwrap_or_default(),
+            bare: element
+                .optional_child::<Bool>("bare")?
+                .is_some_and(|bare| bare.0),
+            git_config: git_config(element)?,
+            templates,
+            tags,
+            repositories,
         })
     }
 }
@@ -16,13 +16,72 @@ pub enum Commands {
         #[command(subcommand)]
         command: InitCommands,
     },
-    Switch,
+    /// Make the server match the config
+    Switch(SwitchArgs),
+    /// Show what `switch` would change without changing
+    /// anything
+    Plan(PlanArgs),
+    /// Check that the config is valid
+    Check {
+        /// Read config.xml from this commit instead of
+        /// from --config
+        #[arg(long = "ref")]
+        rev: Option<String>,
+    },
+    /// Fast-forward repositories from their upstreams
+    Sync {
+        /// Only sync this repository
+        repository: Option<String>,
+        /// Only sync repositories with this tag
+        #[arg(long)]
+        tag: Option<String>,
+    },
+    /// Push the current repository to its mirrors
+    Mirror,
+    /// Convert a repository in the store to a bare one,
+    /// moving its worktree into the trash
+    Convert {
+        repository: String,
+        /// Make the repository bare
+        #[arg(long, required = true)]
+        bare: bool,
+    },
     Search {
-        #[arg(default_value = "")]
+        /// What to search for, like `tag:rust -tag:archived`
+        #[arg(
+            default_value = "",
+            allow_hyphen_values = true
+        )]
         search: String,
+        /// The format to print results in
+        #[arg(long, value_enum, default_value = "xml")]
+        format: crate::search::Format,
+        /// Only show the best N results
+        #[arg(long)]
+        limit: Option<usize>,
     },
 }
 
+#[derive(clap::Args)]
+pub struct SwitchArgs {
+    #[command(flatten)]
+    pub plan: PlanArgs,
+    /// Print what would change without changing anything
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+#[derive(clap::Args)]
+pub struct PlanArgs {
+    /// Trash repositories and remove symlinks that are no
+    /// longer in the config
+    #[arg(long)]
+    pub prune: bool,
+    /// The format to print changes in
+    #[arg(long, value_enum, default_value = "diff")]
+    pub format: crate::plan::Format,
+}
+
 #[derive(clap::Subcommand)]
 pub enum InitCommands {
     Server(InitServerArgs),
@@ -9,6 +9,26 @@ const DEFAULT: &str = "\x1b[1;39m";
 
 pub struct Error(String);
 
+impl Error {
+    /// Creates an error that isn't caused by anything else.
+    pub fn new(
+        kind: &str,
+        detail: impl std::fmt::Display,
+    ) -> Self {
+        Self(format!("{RED}{kind}{DEFAULT}\n\t{detail}"))
+    }
+    /// Adds a line saying where the error happened.
+    pub fn within(
+        self,
+        context: impl std::fmt::Display,
+    ) -> Self {
+        Self(format!(
+            "{}\n\twhile writing {context}",
+            self.0
+        ))
+    }
+}
+
 impl std::fmt::Display for Error {
     fn fmt(
         &self,
@@ -53,6 +73,23 @@ impl Context for [&std::path::Path; 2] {
     }
 }
 
+pub struct Move<'a>(
+    pub &'a std::path::Path,
+    pub &'a std::path::Path,
+);
+
+impl Context for Move<'_> {
+    const KIND: &'static str = "move";
+
+    fn display(self) -> impl std::fmt::Display {
+        format!(
+            "{} -> {}",
+            self.0.display(),
+            self.1.display()
+        )
+    }
+}
+
 impl Context for &std::path::Path {
     const KIND: &'static str = "path";
     fn display(self) -> impl std::fmt::Display {
@@ -68,6 +105,39 @@ impl Context for std::process::Command {
     }
 }
 
+impl Context for &std::process::Command {
+    const KIND: &'static str = "command";
+
+    fn display(self) -> impl std::fmt::Display {
+        format!("{self:?}")
+    }
+}
+
+pub trait CommandExt {
+    /// Runs the command, failing if it exits unsuccessfully.
+    fn run(
+        &mut self,
+    ) -> Result<std::process::Output, Error>;
+}
+
+impl CommandExt for std::process::Command {
+    fn run(
+        &mut self,
+    ) -> Result<std::process::Output, Error> {
+        let output = self.output().with(&*self)?;
+        if !output.status.success() {
+            let status = output.status;
+            let stderr =
+                String::from_utf8_lossy(&output.stderr);
+            let stderr = stderr.trim();
+            return Err(Error(format!(
+                "{RED}Command failed with {status}{DEFAULT}\n\t{self:?}\n{RED}Error:{DEFAULT}\n\t{stderr}"
+            )));
+        }
+        Ok(output)
+    }
+}
+
 pub struct With<T, C: Context> {
     inner: T,
     context: C,
@@ -0,0 +1,73 @@
+// SPDX-FileCopyrightText: 2025 Madeline Baggins <declanbaggins@gmail.com>
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+use std::{fs::File, io::Read, path::Path};
+
+use maddi_xml as xml;
+
+use error::*;
+use xml::FromElement as _;
+
+pub mod cfg;
+pub mod error;
+pub mod plan;
+pub mod query;
+pub mod search;
+pub mod sync;
+
+pub use error::Error;
+
+impl cfg::Config {
+    /// Reads and parses the config file at `path`.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        // Open the configuration file
+        let mut file = File::open(path).with(path)?;
+        // Read in the configuration file
+        let mut source = String::new();
+        file.read_to_string(&mut source).with(path)?;
+        Self::parse(path, &source)
+    }
+    /// Parses a config read from `path`.
+    pub fn parse(
+        path: &Path,
+        source: &str,
+    ) -> Result<Self, Error> {
+        // Create the parser
+        let mut parser = xml::Parser::new(path, source);
+        // Get the first piece of content in the file
+        let content =
+            parser
+                .parse::<Option<
+                    Result<xml::Content, maddi_xml::Error>,
+                >>()
+                .transpose()?;
+        // Ensure the content was an element named 'config'
+        let element = match content {
+            Some(xml::Content::Element(e)) => {
+                if e.name == "config" {
+                    e
+                } else {
+                    return Err(e
+                        .position
+                        .error(
+                            "expected 'config' element"
+                                .into(),
+                        )
+                        .into());
+                }
+            }
+            _ => {
+                return Err(parser
+                    .position
+                    .error(
+                        "expected 'config' element".into(),
+                    )
+                    .into())
+            }
+        };
+        // Get the config from the xml ast
+        let config = cfg::Config::from_element(&element)?;
+        Ok(config)
+    }
+}
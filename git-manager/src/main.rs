@@ -3,66 +3,18 @@
 // SPDX-License-Identifier: GPL-3.0-only
 
 use std::{
-    fs::File,
-    io::{Read, Write},
-    path::Path,
+    io::Write,
+    path::PathBuf,
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
 };
 
-use maddi_xml as xml;
-
 use clap::Parser as _;
-use error::*;
-use xml::FromElement as _;
+use maddi_git_manager::{
+    cfg, error::*, plan, query::Query, search, sync,
+};
 
-mod cfg;
 mod cli;
-mod error;
-
-impl cfg::Config {
-    fn load(path: &Path) -> Result<Self, Error> {
-        // Open the configuration file
-        let mut file = File::open(path).with(path)?;
-        // Read in the configuration file
-        let mut source = String::new();
-        file.read_to_string(&mut source).with(path)?;
-        // Create the parser
-        let mut parser = xml::Parser::new(path, &source);
-        // Get the first piece of content in the file
-        let content =
-            parser
-                .parse::<Option<
-                    Result<xml::Content, maddi_xml::Error>,
-                >>()
-                .transpose()?;
-        // Ensure the content was an element named 'config'
-        let element = match content {
-            Some(xml::Content::Element(e)) => {
-                if e.name == "config" {
-                    e
-                } else {
-                    return Err(e
-                        .position
-                        .error(
-                            "expected 'config' element"
-                                .into(),
-                        )
-                        .into());
-                }
-            }
-            _ => {
-                return Err(parser
-                    .position
-                    .error(
-                        "expected 'config' element".into(),
-                    )
-                    .into())
-            }
-        };
-        // Get the config from the xml ast
-        let config = cfg::Config::from_element(&element)?;
-        Ok(config)
-    }
-}
 
 fn main() {
     // Get the args supplied to the program
@@ -70,6 +22,7 @@ fn main() {
     // Run the program, printing out any errors
     if let Err(err) = run(args) {
         println!("{err}");
+        std::process::exit(1);
     }
 }
 
@@ -79,10 +32,34 @@ fn run(args: cli::Args) -> Result<(), Error> {
         cli::Commands::Init {
             command: cli::InitCommands::Server(init_args),
         } => handle_init(init_args)?,
-        cli::Commands::Switch => handle_switch(args)?,
-        cli::Commands::Search { ref search } => {
-            handle_search(&args, search)?
+        cli::Commands::Switch(ref switch) => handle_switch(
+            &args,
+            &switch.plan,
+            switch.dry_run,
+        )?,
+        cli::Commands::Plan(ref plan) => {
+            handle_switch(&args, plan, true)?
+        }
+        cli::Commands::Check { ref rev } => {
+            handle_check(&args, rev.as_deref())?
         }
+        cli::Commands::Sync {
+            ref repository,
+            ref tag,
+        } => handle_sync(
+            &args,
+            repository.as_deref(),
+            tag.as_deref(),
+        )?,
+        cli::Commands::Mirror => handle_mirror()?,
+        cli::Commands::Convert {
+            ref repository, ..
+        } => handle_convert(&args, repository)?,
+        cli::Commands::Search {
+            ref search,
+            format,
+            limit,
+        } => handle_search(&args, search, format, limit)?,
     }
     Ok(())
 }
@@ -102,21 +79,12 @@ fn handle_init(
             "$SYMLINKS",
             args.symlinks.to_str().unwrap(),
         )
-        .replace(
-            "$GIT_MANAGER",
-            std::env::current_exe()
-                .unwrap()
-                .to_str()
-                .unwrap(),
-        )
         .replace("$BRANCH", &args.branch)
         .replace("$STORE", args.store.to_str().unwrap());
     // Initialize the admin repository
-    let admin = cfg::Repository::admin().switch(
-        &args.branch,
-        &args.symlinks,
-        &args.store,
-    )?;
+    let path = args.store.join("admin/config.xml");
+    let admin = cfg::Repository::admin()
+        .switch(&cfg::Config::parse(&path, &config)?)?;
     // Write the example configuration file
     std::fs::File::options()
         .write(true)
@@ -128,36 +96,190 @@ fn handle_init(
     Ok(())
 }
 
+fn handle_check(
+    args: &cli::Args,
+    rev: Option<&str>,
+) -> Result<(), Error> {
+    match rev {
+        // Read the config straight out of the commit
+        Some(rev) => {
+            let file = format!("{rev}:config.xml");
+            let output = Command::new("git")
+                .arg("show")
+                .arg(&file)
+                .run()?;
+            let source =
+                String::from_utf8_lossy(&output.stdout);
+            let config = cfg::Config::parse(
+                &PathBuf::from(file),
+                &source,
+            )?;
+            // Plan everything a switch would, with hook
+            // files from the same commit
+            config.plan(true, &|path| {
+                if path.is_absolute() {
+                    return cfg::read_file(path);
+                }
+                let output = Command::new("git")
+                    .arg("show")
+                    .arg(format!(
+                        "{rev}:{}",
+                        path.display()
+                    ))
+                    .run()?;
+                Ok(String::from_utf8_lossy(&output.stdout)
+                    .into())
+            })?;
+        }
+        None => {
+            cfg::Config::load(&args.config)?
+                .plan(true, &cfg::read_file)?;
+        }
+    }
+    println!("config is valid");
+    Ok(())
+}
+
+fn handle_sync(
+    args: &cli::Args,
+    repository: Option<&str>,
+    tag: Option<&str>,
+) -> Result<(), Error> {
+    // Try to open the configuration file
+    let config = cfg::Config::load(&args.config)?;
+    // Find the repositories to sync
+    let selected =
+        config.repositories.iter().filter(|repo| {
+            repository
+                .is_none_or(|name| repo.name() == name)
+                && tag.is_none_or(|tag| {
+                    repo.tags().iter().any(|t| t == tag)
+                })
+        });
+    let mut failed = 0;
+    for repo in selected {
+        let Some(upstream) = repo.upstream() else {
+            if repository.is_some() {
+                return Err(Error::new(
+                    "No upstream",
+                    format!(
+                        "'{}' has no upstream",
+                        repo.name()
+                    ),
+                ));
+            }
+            continue;
+        };
+        let path = config.store.join(repo.name());
+        let branches =
+            upstream.branches(repo.branch(&config));
+        for (branch, outcome) in
+            sync::sync(&path, &branches)?
+        {
+            println!("{}: {branch} {outcome}", repo.name());
+            if outcome.failed() {
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(Error::new(
+            "Sync incomplete",
+            format!(
+                "{failed} branches could not be synced"
+            ),
+        ));
+    }
+    Ok(())
+}
+
+fn handle_mirror() -> Result<(), Error> {
+    // Find all the mirrors
+    let remotes = plan::remotes(
+        Command::new("git"),
+        cfg::MIRROR_REMOTE,
+    )?;
+    // Push to each of them, logging any failures
+    for remote in remotes.values() {
+        let mut push = Command::new("git");
+        push.arg("push").arg(&remote.name);
+        let output = push.output().with(&push)?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|now| now.as_secs())
+            .unwrap_or_default();
+        let url = &remote.url;
+        if output.status.success() {
+            println!("[{now}] pushed to {url}");
+        } else {
+            let stderr =
+                String::from_utf8_lossy(&output.stderr);
+            println!(
+                "[{now}] failed to push to {url}\n{stderr}"
+            );
+        }
+    }
+    Ok(())
+}
+
+fn handle_convert(
+    args: &cli::Args,
+    repository: &str,
+) -> Result<(), Error> {
+    // Try to open the configuration file
+    let config = cfg::Config::load(&args.config)?;
+    let action = config.convert_to_bare(repository)?;
+    action.apply()?;
+    println!("{action}");
+    // Remind the user to update the config to match
+    let configured = config
+        .repositories
+        .iter()
+        .find(|repo| repo.name() == repository);
+    if configured.is_some_and(|repo| !repo.bare(&config)) {
+        println!(
+            "set bare=\"true\" on '{repository}' in the config before the next switch"
+        );
+    }
+    Ok(())
+}
+
 fn handle_search(
     args: &cli::Args,
     search: &str,
+    format: search::Format,
+    limit: Option<usize>,
 ) -> Result<(), Error> {
+    let query = Query::parse(search)?;
     // Try to open the configuration file
     let config = cfg::Config::load(&args.config)?;
     // Print all the results out to stdout
-    let results = config.repositories.iter().filter_map(
-        |repository| {
-            repository
-                .smartget_filter_map(search, &config.store)
-        },
-    );
-    for result in results {
-        println!("{}", result);
+    let mut results = query.rank(&config.repositories);
+    if let Some(limit) = limit {
+        results.truncate(limit);
     }
+    print!("{}", search::render(&config, &results, format));
     Ok(())
 }
 
-fn handle_switch(args: cli::Args) -> Result<(), Error> {
+fn handle_switch(
+    args: &cli::Args,
+    plan: &cli::PlanArgs,
+    dry_run: bool,
+) -> Result<(), Error> {
     // Try to open the configuration file
     let config = cfg::Config::load(&args.config)?;
-    // Reconfigure everything to match the config
-    for repo in config.repositories {
-        // Ensure the repository exists
-        repo.switch(
-            &config.branch,
-            &config.symlinks,
-            &config.store,
-        )?;
+    // Work out what needs to change to match the config,
+    // cleaning up anything removed from it
+    let prune =
+        plan.prune || config.prune == cfg::Prune::Always;
+    let actions = config.plan(prune, &cfg::read_file)?;
+    // Make the changes unless this is a dry run
+    if !dry_run {
+        for action in &actions {
+            action.apply()?;
+        }
     }
+    plan::print(&actions, plan.format);
     Ok(())
 }
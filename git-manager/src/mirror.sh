@@ -0,0 +1,8 @@
+#!/bin/sh
+# Generated by git-manager: push to this repository's
+# mirrors in the background, then run the configured
+# post-receive hook.
+"{{git-manager}}" mirror </dev/null >>mirror.log 2>&1 &
+if [ -x "$0.user" ]; then
+  exec "$0.user"
+fi
@@ -0,0 +1,1186 @@
+// SPDX-FileCopyrightText: 2025 Madeline Baggins <declanbaggins@gmail.com>
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{
+    error::{CommandExt, Move},
+    Error, ResultExt,
+};
+
+/// A remote git-manager keeps configured in a repository.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Remote {
+    pub name: String,
+    pub url: String,
+    pub fetch: Vec<String>,
+    pub push: Vec<String>,
+}
+
+/// Reads every remote whose name starts with `prefix`
+/// using `git`, a git command for the repository.
+pub fn remotes(
+    mut git: Command,
+    prefix: &str,
+) -> Result<BTreeMap<String, Remote>, Error> {
+    let pattern =
+        format!("^remote\\.{}", prefix.replace('.', "\\."));
+    git.args(["config", "--get-regexp", &pattern]);
+    // Git fails when nothing matches
+    let output = git.output().with(&git)?;
+    let output = String::from_utf8_lossy(&output.stdout);
+    let mut remotes = BTreeMap::new();
+    for line in output.lines() {
+        let Some((key, value)) = line.split_once(' ')
+        else {
+            continue;
+        };
+        let Some((name, variable)) = key
+            .strip_prefix("remote.")
+            .and_then(|key| key.rsplit_once('.'))
+        else {
+            continue;
+        };
+        let remote = remotes
+            .entry(name.to_string())
+            .or_insert_with(|| Remote {
+                name: name.into(),
+                url: String::new(),
+                fetch: vec![],
+                push: vec![],
+            });
+        match variable {
+            "url" => remote.url = value.into(),
+            "fetch" => remote.fetch.push(value.into()),
+            "push" => remote.push.push(value.into()),
+            _ => {}
+        }
+    }
+    Ok(remotes)
+}
+
+/// The git config key listing every key git-manager set,
+/// so it knows which ones to unset when they're removed.
+const MANAGED_CONFIG: &str = "gitmanager.managed";
+
+/// A git command that runs against a repository, whether
+/// it's bare or not.
+pub fn git(repository: &Path) -> Command {
+    let git_dir = if repository.join(".git").exists() {
+        ".git"
+    } else {
+        "."
+    };
+    let mut command = Command::new("git");
+    command
+        .arg(format!("--git-dir={git_dir}"))
+        .current_dir(repository);
+    command
+}
+
+/// A single change `switch` makes to the server.
+#[derive(Debug)]
+pub enum Action {
+    /// Create a new repository with `git init`
+    Init {
+        path: PathBuf,
+        branch: String,
+        bare: bool,
+    },
+    /// Create a new repository by cloning another, starting
+    /// on `branch` if it has one
+    Clone {
+        path: PathBuf,
+        url: String,
+        remote: String,
+        branch: String,
+        bare: bool,
+    },
+    /// Set a git config value in a repository
+    SetConfig {
+        repository: PathBuf,
+        key: String,
+        value: String,
+        previous: Option<String>,
+    },
+    /// Unset a git config value that is no longer
+    /// configured
+    UnsetConfig {
+        repository: PathBuf,
+        key: String,
+        previous: String,
+    },
+    /// Point HEAD at a different default branch
+    SetHead {
+        repository: PathBuf,
+        branch: String,
+        previous: String,
+    },
+    /// Leave HEAD alone because the default branch doesn't
+    /// exist yet
+    KeepHead {
+        repository: PathBuf,
+        branch: String,
+        current: String,
+    },
+    /// Create or update a remote
+    SetRemote {
+        repository: PathBuf,
+        remote: Remote,
+        previous: Option<Remote>,
+    },
+    /// Remove a remote that is no longer configured
+    RemoveRemote { repository: PathBuf, remote: Remote },
+    /// Create or overwrite a hook
+    WriteHook {
+        path: PathBuf,
+        content: String,
+        previous: Option<String>,
+    },
+    /// Write the repository's description file
+    Describe {
+        path: PathBuf,
+        description: String,
+        previous: Option<String>,
+    },
+    /// Delete a hook that is no longer configured
+    DeleteHook { path: PathBuf, previous: String },
+    /// Create or replace a symlink
+    Symlink {
+        link: PathBuf,
+        target: PathBuf,
+        previous: Option<PathBuf>,
+    },
+    /// Move a renamed repository to its new name
+    Rename { from: PathBuf, to: PathBuf },
+    /// Move a removed repository into the trash
+    Trash { from: PathBuf, to: PathBuf },
+    /// Remove a symlink that is no longer configured
+    RemoveSymlink { link: PathBuf, target: PathBuf },
+    /// Make a repository bare, moving its worktree into
+    /// the trash
+    ConvertToBare { path: PathBuf, worktree: PathBuf },
+}
+
+impl Action {
+    /// Plans setting `key` to `value` in a repository's
+    /// local git config if it isn't already.
+    pub fn set_config(
+        repository: &Path,
+        key: &str,
+        value: &str,
+    ) -> Result<Option<Self>, Error> {
+        let previous = get_config(repository, key)?;
+        if previous.as_deref() == Some(value) {
+            return Ok(None);
+        }
+        Ok(Some(Action::SetConfig {
+            repository: repository.into(),
+            key: key.into(),
+            value: value.into(),
+            previous,
+        }))
+    }
+    /// Plans making a repository's local git config contain
+    /// `desired`, unsetting anything git-manager set before
+    /// that is no longer desired.
+    pub fn git_config(
+        repository: &Path,
+        desired: BTreeMap<String, String>,
+    ) -> Result<Vec<Self>, Error> {
+        let mut actions = vec![];
+        for (key, value) in &desired {
+            actions.extend(Action::set_config(
+                repository, key, value,
+            )?);
+        }
+        // Unset whatever was removed from the config
+        let managed =
+            get_config(repository, MANAGED_CONFIG)?
+                .unwrap_or_default();
+        for key in managed.split_whitespace() {
+            if desired.contains_key(key) {
+                continue;
+            }
+            if let Some(previous) =
+                get_config(repository, key)?
+            {
+                actions.push(Action::UnsetConfig {
+                    repository: repository.into(),
+                    key: key.into(),
+                    previous,
+                });
+            }
+        }
+        // Remember what was set for next time
+        let keys = desired
+            .into_keys()
+            .collect::<Vec<_>>()
+            .join(" ");
+        if keys != managed {
+            actions.extend(Action::set_config(
+                repository,
+                MANAGED_CONFIG,
+                &keys,
+            )?);
+        }
+        Ok(actions)
+    }
+    /// Plans pointing a repository's HEAD at `branch`,
+    /// which only happens if the branch exists.
+    pub fn set_head(
+        repository: &Path,
+        branch: &str,
+    ) -> Result<Option<Self>, Error> {
+        if !repository.exists() {
+            return Ok(None);
+        }
+        let mut cmd = git(repository);
+        cmd.args(["symbolic-ref", "--quiet", "HEAD"]);
+        let output = cmd.output().with(cmd)?;
+        let head = String::from_utf8_lossy(&output.stdout);
+        let current = head.trim();
+        let current = current
+            .strip_prefix("refs/heads/")
+            .unwrap_or(current);
+        if current == branch {
+            return Ok(None);
+        }
+        let mut cmd = git(repository);
+        cmd.args([
+            "rev-parse",
+            "--verify",
+            "--quiet",
+            &format!("refs/heads/{branch}"),
+        ]);
+        let exists =
+            cmd.output().with(cmd)?.status.success();
+        let repository = repository.into();
+        let branch = branch.into();
+        let current = current.into();
+        Ok(Some(match exists {
+            true => Action::SetHead {
+                repository,
+                branch,
+                previous: current,
+            },
+            false => Action::KeepHead {
+                repository,
+                branch,
+                current,
+            },
+        }))
+    }
+    /// Plans making the remotes starting with `prefix` in a
+    /// repository match `desired`.
+    pub fn remotes(
+        repository: &Path,
+        prefix: &str,
+        desired: Vec<Remote>,
+    ) -> Result<Vec<Self>, Error> {
+        let mut current = if repository.exists() {
+            remotes(git(repository), prefix)?
+        } else {
+            BTreeMap::new()
+        };
+        let mut actions = vec![];
+        for remote in desired {
+            let previous = current.remove(&remote.name);
+            if previous.as_ref() != Some(&remote) {
+                actions.push(Action::SetRemote {
+                    repository: repository.into(),
+                    remote,
+                    previous,
+                });
+            }
+        }
+        for remote in current.into_values() {
+            actions.push(Action::RemoveRemote {
+                repository: repository.into(),
+                remote,
+            });
+        }
+        Ok(actions)
+    }
+    /// Plans making the file at `path` contain `content`,
+    /// or deleting it if there is no content.
+    pub fn write_hook(
+        path: &Path,
+        content: Option<String>,
+    ) -> Result<Option<Self>, Error> {
+        let previous = match std::fs::read_to_string(path) {
+            Ok(previous) => Some(previous),
+            Err(err)
+                if err.kind()
+                    == std::io::ErrorKind::NotFound =>
+            {
+                None
+            }
+            Err(err) => Err(err).with(path)?,
+        };
+        Ok(match (content, previous) {
+            (None, None) => None,
+            (None, Some(previous)) => {
+                Some(Action::DeleteHook {
+                    path: path.into(),
+                    previous,
+                })
+            }
+            (Some(content), previous) => (previous
+                .as_ref()
+                != Some(&content))
+            .then(|| Action::WriteHook {
+                path: path.into(),
+                content,
+                previous,
+            }),
+        })
+    }
+    /// Plans writing `description` to the description file
+    /// at `path`.
+    pub fn describe(
+        path: &Path,
+        description: &str,
+    ) -> Option<Self> {
+        let description = format!("{description}\n");
+        let previous = std::fs::read_to_string(path).ok();
+        (previous.as_ref() != Some(&description)).then(
+            || Action::Describe {
+                path: path.into(),
+                description,
+                previous,
+            },
+        )
+    }
+    /// Plans making `link` a symlink to `target`.
+    pub fn symlink(
+        link: &Path,
+        target: &Path,
+    ) -> Option<Self> {
+        let previous = std::fs::read_link(link).ok();
+        (previous.as_deref() != Some(target)).then(|| {
+            Action::Symlink {
+                link: link.into(),
+                target: target.into(),
+                previous,
+            }
+        })
+    }
+    /// Moves an action planned against the repository at
+    /// `from` so that it applies to the repository at `to`.
+    pub fn moved(self, from: &Path, to: &Path) -> Self {
+        let rebase =
+            |path: PathBuf| match path.strip_prefix(from) {
+                Ok(tail) if tail.as_os_str().is_empty() => {
+                    to.into()
+                }
+                Ok(tail) => to.join(tail),
+                Err(_) => path,
+            };
+        match self {
+            Action::SetConfig {
+                repository,
+                key,
+                value,
+                previous,
+            } => Action::SetConfig {
+                repository: rebase(repository),
+                key,
+                value,
+                previous,
+            },
+            Action::UnsetConfig {
+                repository,
+                key,
+                previous,
+            } => Action::UnsetConfig {
+                repository: rebase(repository),
+                key,
+                previous,
+            },
+            Action::SetHead {
+                repository,
+                branch,
+                previous,
+            } => Action::SetHead {
+                repository: rebase(repository),
+                branch,
+                previous,
+            },
+            Action::KeepHead {
+                repository,
+                branch,
+                current,
+            } => Action::KeepHead {
+                repository: rebase(repository),
+                branch,
+                current,
+            },
+            Action::SetRemote {
+                repository,
+                remote,
+                previous,
+            } => Action::SetRemote {
+                repository: rebase(repository),
+                remote,
+                previous,
+            },
+            Action::RemoveRemote { repository, remote } => {
+                Action::RemoveRemote {
+                    repository: rebase(repository),
+                    remote,
+                }
+            }
+            Action::WriteHook {
+                path,
+                content,
+                previous,
+            } => Action::WriteHook {
+                path: rebase(path),
+                content,
+                previous,
+            },
+            Action::DeleteHook { path, previous } => {
+                Action::DeleteHook {
+                    path: rebase(path),
+                    previous,
+                }
+            }
+            Action::Describe {
+                path,
+                description,
+                previous,
+            } => Action::Describe {
+                path: rebase(path),
+                description,
+                previous,
+            },
+            action => action,
+        }
+    }
+    /// Makes the change this action describes.
+    pub fn apply(&self) -> Result<(), Error> {
+        match self {
+            Action::Init { path, branch, bare } => {
+                let mut command = Command::new("git");
+                if *bare {
+                    command.arg("init").arg("--bare");
+                } else {
+                    command
+                        .arg("--git-dir=.git")
+                        .arg("init");
+                }
+                command.arg("-b").arg(branch).arg(path);
+                command.output().with(command)?;
+            }
+            Action::Clone {
+                path,
+                url,
+                remote,
+                branch,
+                bare,
+            } => {
+                let mut command = Command::new("git");
+                command.arg("clone");
+                if *bare {
+                    command.arg("--bare");
+                }
+                // Otherwise the clone stays on the
+                // upstream's HEAD
+                let mut heads = Command::new("git");
+                heads
+                    .args(["ls-remote", "--exit-code", url])
+                    .arg(format!("refs/heads/{branch}"));
+                if heads
+                    .output()
+                    .with(&heads)?
+                    .status
+                    .success()
+                {
+                    command.arg("--branch").arg(branch);
+                }
+                command
+                    .arg("--origin")
+                    .arg(remote)
+                    .arg(url)
+                    .arg(path)
+                    .run()?;
+            }
+            Action::SetConfig {
+                repository,
+                key,
+                value,
+                ..
+            } => {
+                git(repository)
+                    .args(["config", "--local", key, value])
+                    .run()?;
+            }
+            Action::UnsetConfig {
+                repository, key, ..
+            } => {
+                git(repository)
+                    .args([
+                        "config",
+                        "--local",
+                        "--unset-all",
+                        key,
+                    ])
+                    .run()?;
+            }
+            Action::SetHead {
+                repository, branch, ..
+            } => {
+                git(repository)
+                    .args([
+                        "symbolic-ref",
+                        "HEAD",
+                        &format!("refs/heads/{branch}"),
+                    ])
+                    .run()?;
+            }
+            Action::KeepHead { .. } => {}
+            Action::SetRemote {
+                repository,
+                remote,
+                ..
+            } => {
+                // Start from scratch, which fails if the
+                // remote doesn't exist yet
+                let section =
+                    format!("remote.{}", remote.name);
+                let mut remove = git(repository);
+                remove.args([
+                    "config",
+                    "--remove-section",
+                    &section,
+                ]);
+                remove.output().with(remove)?;
+                git(repository)
+                    .args([
+                        "config",
+                        &format!("{section}.url"),
+                        &remote.url,
+                    ])
+                    .run()?;
+                let specs = [
+                    ("fetch", &remote.fetch),
+                    ("push", &remote.push),
+                ];
+                for (kind, specs) in specs {
+                    for spec in specs {
+                        git(repository)
+                            .args([
+                                "config",
+                                "--add",
+                                &format!(
+                                    "{section}.{kind}"
+                                ),
+                                spec,
+                            ])
+                            .run()?;
+                    }
+                }
+            }
+            Action::RemoveRemote { repository, remote } => {
+                let section =
+                    format!("remote.{}", remote.name);
+                git(repository)
+                    .args([
+                        "config",
+                        "--remove-section",
+                        &section,
+                    ])
+                    .run()?;
+            }
+            Action::WriteHook { path, content, .. } => {
+                let path = path.as_path();
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)
+                        .with(parent)?;
+                }
+                let mut file = std::fs::File::options()
+                    .create(true)
+                    .write(true)
+                    .truncate(true)
+                    .open(path)
+                    .with(path)?;
+                file.write_all(content.as_bytes())
+                    .with(path)?;
+                // Make the file executable
+                Command::new("chmod")
+                    .arg("+x")
+                    .arg(path)
+                    .output()
+                    .with(path)?;
+            }
+            Action::Describe {
+                path, description, ..
+            } => {
+                std::fs::write(path, description)
+                    .with(path.as_path())?;
+            }
+            Action::DeleteHook { path, .. }
+            | Action::RemoveSymlink {
+                link: path, ..
+            } => {
+                std::fs::remove_file(path)
+                    .with(path.as_path())?;
+            }
+            Action::Symlink {
+                link,
+                target,
+                previous,
+            } => {
+                // Ensure the parent directory exists
+                let parent = link.parent().unwrap();
+                std::fs::create_dir_all(parent)
+                    .with(parent)?;
+                // If the symlink exists, delete it.
+                if previous.is_some() || link.exists() {
+                    std::fs::remove_file(link)
+                        .with(link.as_path())?;
+                }
+                // Create the symlink
+                std::os::unix::fs::symlink(target, link)
+                    .with([
+                        target.as_path(),
+                        link.as_path(),
+                    ])?;
+            }
+            Action::Rename { from, to } => {
+                std::fs::rename(from, to)
+                    .with(Move(from, to))?;
+            }
+            Action::Trash { from, to } => {
+                let trash = to.parent().unwrap();
+                std::fs::create_dir_all(trash)
+                    .with(trash)?;
+                std::fs::rename(from, to)
+                    .with(Move(from, to))?;
+            }
+            Action::ConvertToBare { path, worktree } => {
+                // Move the worktree out of the way, then
+                // put its git directory in its place
+                let trash = worktree.parent().unwrap();
+                std::fs::create_dir_all(trash)
+                    .with(trash)?;
+                std::fs::rename(path, worktree)
+                    .with(Move(path, worktree))?;
+                let git_dir = worktree.join(".git");
+                std::fs::rename(&git_dir, path)
+                    .with(Move(&git_dir, path))?;
+                git(path)
+                    .args(["config", "core.bare", "true"])
+                    .run()?;
+            }
+        }
+        Ok(())
+    }
+    /// The lines of detail shown below the summary.
+    fn details(&self) -> Vec<String> {
+        match self {
+            Action::WriteHook {
+                content, previous, ..
+            } => diff(
+                previous.as_deref().unwrap_or(""),
+                content,
+            ),
+            Action::DeleteHook { previous, .. } => {
+                diff(previous, "")
+            }
+            Action::Describe {
+                description,
+                previous,
+                ..
+            } => diff(
+                previous.as_deref().unwrap_or(""),
+                description,
+            ),
+            Action::SetRemote { remote, .. } => {
+                let fetch = remote
+                    .fetch
+                    .iter()
+                    .map(|spec| format!("fetch {spec}"));
+                let push = remote
+                    .push
+                    .iter()
+                    .map(|spec| format!("push {spec}"));
+                fetch.chain(push).collect()
+            }
+            _ => vec![],
+        }
+    }
+    /// Renders the action as a JSON object.
+    pub fn json(&self) -> String {
+        let path = |path: &Path| {
+            json_string(&path.display().to_string())
+        };
+        let text = |text: Option<&str>| {
+            text.map(json_string)
+                .unwrap_or_else(|| "null".into())
+        };
+        match self {
+            Action::Init {
+                path: p,
+                branch,
+                bare,
+            } => format!(
+                r#"{{"action":"init","path":{},"branch":{},"bare":{bare}}}"#,
+                path(p),
+                json_string(branch)
+            ),
+            Action::Clone {
+                path: p,
+                url,
+                remote,
+                branch,
+                bare,
+            } => format!(
+                r#"{{"action":"clone","path":{},"url":{},"remote":{},"branch":{},"bare":{bare}}}"#,
+                path(p),
+                json_string(url),
+                json_string(remote),
+                json_string(branch)
+            ),
+            Action::SetConfig {
+                repository,
+                key,
+                value,
+                previous,
+            } => format!(
+                r#"{{"action":"set-config","repository":{},"key":{},"value":{},"previous":{}}}"#,
+                path(repository),
+                json_string(key),
+                json_string(value),
+                text(previous.as_deref())
+            ),
+            Action::UnsetConfig {
+                repository,
+                key,
+                previous,
+            } => format!(
+                r#"{{"action":"unset-config","repository":{},"key":{},"previous":{}}}"#,
+                path(repository),
+                json_string(key),
+                json_string(previous)
+            ),
+            Action::SetHead {
+                repository,
+                branch,
+                previous,
+            } => format!(
+                r#"{{"action":"set-head","repository":{},"branch":{},"previous":{}}}"#,
+                path(repository),
+                json_string(branch),
+                json_string(previous)
+            ),
+            Action::KeepHead {
+                repository,
+                branch,
+                current,
+            } => format!(
+                r#"{{"action":"keep-head","repository":{},"branch":{},"current":{}}}"#,
+                path(repository),
+                json_string(branch),
+                json_string(current)
+            ),
+            Action::SetRemote {
+                repository,
+                remote,
+                previous,
+            } => format!(
+                r#"{{"action":"set-remote","repository":{},"name":{},"url":{},"fetch":{},"push":{},"previous":{}}}"#,
+                path(repository),
+                json_string(&remote.name),
+                json_string(&remote.url),
+                json_list(&remote.fetch),
+                json_list(&remote.push),
+                text(
+                    previous
+                        .as_ref()
+                        .map(|p| p.url.as_str())
+                )
+            ),
+            Action::RemoveRemote { repository, remote } => {
+                format!(
+                    r#"{{"action":"remove-remote","repository":{},"name":{},"url":{}}}"#,
+                    path(repository),
+                    json_string(&remote.name),
+                    json_string(&remote.url)
+                )
+            }
+            Action::WriteHook {
+                path: p,
+                content,
+                previous,
+            } => format!(
+                r#"{{"action":"write-hook","path":{},"content":{},"previous":{}}}"#,
+                path(p),
+                json_string(content),
+                text(previous.as_deref())
+            ),
+            Action::Describe {
+                path: p,
+                description,
+                previous,
+            } => format!(
+                r#"{{"action":"describe","path":{},"description":{},"previous":{}}}"#,
+                path(p),
+                json_string(description),
+                text(previous.as_deref())
+            ),
+            Action::DeleteHook { path: p, previous } => {
+                format!(
+                    r#"{{"action":"delete-hook","path":{},"previous":{}}}"#,
+                    path(p),
+                    json_string(previous)
+                )
+            }
+            Action::Symlink {
+                link,
+                target,
+                previous,
+            } => format!(
+                r#"{{"action":"symlink","link":{},"target":{},"previous":{}}}"#,
+                path(link),
+                path(target),
+                previous
+                    .as_deref()
+                    .map(path)
+                    .unwrap_or_else(|| "null".into())
+            ),
+            Action::Rename { from, to } => format!(
+                r#"{{"action":"rename","from":{},"to":{}}}"#,
+                path(from),
+                path(to)
+            ),
+            Action::Trash { from, to } => format!(
+                r#"{{"action":"trash","from":{},"to":{}}}"#,
+                path(from),
+                path(to)
+            ),
+            Action::RemoveSymlink { link, target } => {
+                format!(
+                    r#"{{"action":"remove-symlink","link":{},"target":{}}}"#,
+                    path(link),
+                    path(target)
+                )
+            }
+            Action::ConvertToBare { path: p, worktree } => {
+                format!(
+                    r#"{{"action":"convert-to-bare","path":{},"worktree":{}}}"#,
+                    path(p),
+                    path(worktree)
+                )
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Action {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        match self {
+            Action::Init { path, branch, bare } => write!(
+                f,
+                "+ init {}repository {} on '{branch}'",
+                if *bare { "bare " } else { "" },
+                path.display()
+            ),
+            Action::Clone {
+                path,
+                url,
+                branch,
+                bare,
+                ..
+            } => write!(
+                f,
+                "+ clone {}repository {} from {url} on '{branch}'",
+                if *bare { "bare " } else { "" },
+                path.display()
+            ),
+            Action::SetConfig {
+                repository,
+                key,
+                value,
+                previous,
+            } => {
+                write!(
+                    f,
+                    "~ set {key} = '{value}' in {}",
+                    repository.display()
+                )?;
+                match previous {
+                    Some(previous) => {
+                        write!(f, " (was '{previous}')")
+                    }
+                    None => Ok(()),
+                }
+            }
+            Action::UnsetConfig {
+                repository,
+                key,
+                previous,
+            } => write!(
+                f,
+                "- unset {key} in {} (was '{previous}')",
+                repository.display()
+            ),
+            Action::SetHead {
+                repository,
+                branch,
+                previous,
+            } => write!(
+                f,
+                "~ set HEAD of {} to '{branch}' (was '{previous}')",
+                repository.display()
+            ),
+            Action::KeepHead {
+                repository,
+                branch,
+                current,
+            } => write!(
+                f,
+                "! leave HEAD of {} on '{current}', branch '{branch}' doesn't exist",
+                repository.display()
+            ),
+            Action::SetRemote {
+                repository,
+                remote,
+                previous: None,
+            } => write!(
+                f,
+                "+ add remote '{}' ({}) to {}",
+                remote.name,
+                remote.url,
+                repository.display()
+            ),
+            Action::SetRemote {
+                repository,
+                remote,
+                previous: Some(_),
+            } => write!(
+                f,
+                "~ update remote '{}' ({}) in {}",
+                remote.name,
+                remote.url,
+                repository.display()
+            ),
+            Action::RemoveRemote { repository, remote } => {
+                write!(
+                    f,
+                    "- remove remote '{}' ({}) from {}",
+                    remote.name,
+                    remote.url,
+                    repository.display()
+                )
+            }
+            Action::WriteHook {
+                path,
+                previous: None,
+                ..
+            } => write!(
+                f,
+                "+ create hook {}",
+                path.display()
+            ),
+            Action::WriteHook { path, .. } => {
+                write!(
+                    f,
+                    "~ overwrite hook {}",
+                    path.display()
+                )
+            }
+            Action::Describe { path, .. } => write!(
+                f,
+                "~ write description {}",
+                path.display()
+            ),
+            Action::DeleteHook { path, .. } => {
+                write!(
+                    f,
+                    "- delete hook {}",
+                    path.display()
+                )
+            }
+            Action::Symlink {
+                link,
+                target,
+                previous: None,
+            } => write!(
+                f,
+                "+ symlink {} -> {}",
+                link.display(),
+                target.display()
+            ),
+            Action::Symlink {
+                link,
+                target,
+                previous: Some(previous),
+            } => write!(
+                f,
+                "~ replace symlink {} -> {} (was {})",
+                link.display(),
+                target.display(),
+                previous.display()
+            ),
+            Action::Rename { from, to } => write!(
+                f,
+                "~ rename repository {} -> {}",
+                from.display(),
+                to.display()
+            ),
+            Action::Trash { from, to } => write!(
+                f,
+                "- trash repository {} -> {}",
+                from.display(),
+                to.display()
+            ),
+            Action::RemoveSymlink { link, target } => {
+                write!(
+                    f,
+                    "- remove symlink {} -> {}",
+                    link.display(),
+                    target.display()
+                )
+            }
+            Action::ConvertToBare { path, worktree } => {
+                write!(
+                    f,
+                    "~ convert repository {} to bare (worktree moved to {})",
+                    path.display(),
+                    worktree.display()
+                )
+            }
+        }
+    }
+}
+
+/// Reads a value from a repository's local git config.
+fn get_config(
+    repository: &Path,
+    key: &str,
+) -> Result<Option<String>, Error> {
+    if !repository.exists() {
+        return Ok(None);
+    }
+    let mut cmd = git(repository);
+    cmd.args(["config", "--local", "--get", key]);
+    let output = cmd.output().with(cmd)?;
+    Ok(output.status.success().then(|| {
+        String::from_utf8_lossy(&output.stdout)
+            .trim_end()
+            .to_string()
+    }))
+}
+
+/// How a list of actions is printed.
+#[derive(clap::ValueEnum, Clone, Copy)]
+pub enum Format {
+    /// A human-readable diff
+    Diff,
+    /// A JSON array of actions
+    Json,
+}
+
+/// Prints a list of actions in the given format.
+pub fn print(actions: &[Action], format: Format) {
+    match format {
+        Format::Diff => {
+            for action in actions {
+                println!("{action}");
+                for line in action.details() {
+                    println!("    {line}");
+                }
+            }
+        }
+        Format::Json => {
+            let actions = actions
+                .iter()
+                .map(Action::json)
+                .collect::<Vec<_>>();
+            println!("[{}]", actions.join(","));
+        }
+    }
+}
+
+/// Escapes a string as a JSON string literal.
+pub fn json_string(value: &str) -> String {
+    let mut escaped =
+        String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped
+                .push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// Renders a list of strings as a JSON array.
+fn json_list(values: &[String]) -> String {
+    let values = values
+        .iter()
+        .map(|value| json_string(value))
+        .collect::<Vec<_>>();
+    format!("[{}]", values.join(","))
+}
+
+/// A line-based diff of two texts, with each line prefixed
+/// by '+', '-', or ' '.
+fn diff(old: &str, new: &str) -> Vec<String> {
+    let old = old.lines().collect::<Vec<_>>();
+    let new = new.lines().collect::<Vec<_>>();
+    // Find the longest common subsequence of lines
+    let mut lcs =
+        vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    // Walk the table to build the diff
+    let (mut i, mut j) = (0, 0);
+    let mut lines = vec![];
+    while i < old.len() || j < new.len() {
+        if i < old.len()
+            && j < new.len()
+            && old[i] == new[j]
+        {
+            lines.push(format!("  {}", old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len()
+            && (j == new.len()
+                || lcs[i + 1][j] >= lcs[i][j + 1])
+        {
+            lines.push(format!("- {}", old[i]));
+            i += 1;
+        } else {
+            lines.push(format!("+ {}", new[j]));
+            j += 1;
+        }
+    }
+    lines
+}
@@ -1,4 +1,4 @@
 #!/usr/bin/env bash
 cd ..
 git --git-dir=.git reset --hard
-git-manager switch
+"{{git-manager}}" switch
@@ -0,0 +1,8 @@
+#!/usr/bin/env bash
+# Reject pushes that would leave config.xml invalid
+HEAD=$(git symbolic-ref HEAD)
+while read -r OLD NEW REF; do
+  if [[ $REF = "$HEAD" && ! $NEW =~ ^0+$ ]]; then
+    "{{git-manager}}" check --ref "$NEW" || exit 1
+  fi
+done
@@ -0,0 +1,434 @@
+// SPDX-FileCopyrightText: 2025 Madeline Baggins <declanbaggins@gmail.com>
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+use crate::Error;
+
+/// Something a query can be matched against.
+pub trait Searchable {
+    /// The values of a field, if it has any.
+    fn values(&self, field: Field) -> Vec<String>;
+}
+
+/// A field a query term can be limited to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Name,
+    Tag,
+    Symlink,
+    Description,
+    Homepage,
+    Owner,
+    License,
+}
+
+impl Field {
+    /// Every field a query can search.
+    pub const ALL: [Field; 7] = [
+        Field::Name,
+        Field::Tag,
+        Field::Symlink,
+        Field::Description,
+        Field::Homepage,
+        Field::Owner,
+        Field::License,
+    ];
+    /// The name used for the field in a query.
+    pub fn name(self) -> &'static str {
+        match self {
+            Field::Name => "name",
+            Field::Tag => "tag",
+            Field::Symlink => "symlink",
+            Field::Description => "description",
+            Field::Homepage => "homepage",
+            Field::Owner => "owner",
+            Field::License => "license",
+        }
+    }
+    /// How much a match in the field counts towards a
+    /// result's score.
+    fn weight(self) -> u32 {
+        match self {
+            Field::Name => 3,
+            Field::Tag | Field::Symlink => 2,
+            _ => 1,
+        }
+    }
+}
+
+/// A single condition in a query, like `-tag:archived`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Term {
+    /// The field to search, or every field if `None`
+    pub field: Option<Field>,
+    /// The text to look for
+    pub text: String,
+    /// Whether the term must not match
+    pub negated: bool,
+    /// Whether the text was quoted and must match exactly
+    pub exact: bool,
+}
+
+impl Term {
+    /// How well the term matches its best field, ignoring
+    /// negation.
+    fn score(&self, item: &impl Searchable) -> Option<u32> {
+        let text = self.text.to_lowercase();
+        // Only exclude things that really match
+        let exact = self.exact || self.negated;
+        let fields = match self.field {
+            Some(field) => vec![field],
+            None => Field::ALL.to_vec(),
+        };
+        let mut best = None;
+        for field in fields {
+            for value in item.values(field) {
+                let value = value.to_lowercase();
+                let score = match field {
+                    // Tags are matched whole
+                    Field::Tag if self.field.is_some() => {
+                        (value == text).then_some(EXACT)
+                    }
+                    _ if exact => substring(&text, &value),
+                    _ => fuzzy(&text, &value),
+                };
+                let score = score
+                    .map(|score| score * field.weight());
+                best = best.max(score);
+            }
+        }
+        best
+    }
+}
+
+/// The score for text that matches a value exactly.
+const EXACT: u32 = 1000;
+
+/// Scores `text` appearing somewhere in `value`, with
+/// matches at the start scoring higher.
+fn substring(text: &str, value: &str) -> Option<u32> {
+    if value == text {
+        Some(EXACT)
+    } else if value.starts_with(text) {
+        Some(800)
+    } else if value.contains(text) {
+        Some(600)
+    } else {
+        None
+    }
+}
+
+/// Scores how well `text` matches `value`, tolerating
+/// skipped characters and small typos.
+fn fuzzy(text: &str, value: &str) -> Option<u32> {
+    if let Some(score) = substring(text, value) {
+        return Some(score);
+    }
+    let text = text.chars().collect::<Vec<_>>();
+    // Every character in order, with a few gaps
+    let mut gaps = 0;
+    let mut matched = 0;
+    for c in value.chars() {
+        if matched == text.len() {
+            break;
+        }
+        if c == text[matched] {
+            matched += 1;
+        } else if matched > 0 {
+            gaps += 1;
+        }
+    }
+    if matched == text.len() && gaps <= 2 * text.len() {
+        // Long queries can have a lot of gaps, but still
+        // beat any typo
+        let gaps = gaps.min(19) as u32;
+        return Some(400 - 10 * gaps);
+    }
+    // A word with a typo or two, or the start of one
+    let allowed = match text.len() {
+        0..=2 => 0,
+        3..=5 => 1,
+        _ => 2,
+    };
+    let words = value
+        .split(|c: char| !c.is_alphanumeric())
+        .chain([value])
+        .filter(|word| !word.is_empty());
+    words
+        .filter_map(|word| {
+            let word = word.chars().collect::<Vec<_>>();
+            let prefix =
+                &word[..text.len().min(word.len())];
+            let typos = distance(&text, &word)
+                .min(distance(&text, prefix));
+            (typos <= allowed)
+                .then(|| 200 - 50 * typos as u32)
+        })
+        .max()
+}
+
+/// The number of insertions, deletions, substitutions and
+/// swaps of neighbouring characters that turn `a` into `b`.
+fn distance(a: &[char], b: &[char]) -> usize {
+    let mut table = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in table.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in table[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut best = (table[i - 1][j] + 1)
+                .min(table[i][j - 1] + 1)
+                .min(table[i - 1][j - 1] + cost);
+            if i > 1
+                && j > 1
+                && a[i - 1] == b[j - 2]
+                && a[i - 2] == b[j - 1]
+            {
+                best = best.min(table[i - 2][j - 2] + 1);
+            }
+            table[i][j] = best;
+        }
+    }
+    table[a.len()][b.len()]
+}
+
+/// A parsed search, matching items that satisfy all of
+/// its terms.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Query {
+    pub terms: Vec<Term>,
+}
+
+impl Query {
+    /// Parses a query like
+    /// `tag:rust name:cli -tag:archived "exact phrase"`.
+    pub fn parse(query: &str) -> Result<Self, Error> {
+        let invalid = |detail: String| {
+            Error::new("Invalid query", detail)
+        };
+        let mut terms = vec![];
+        let mut chars = query.chars().peekable();
+        loop {
+            // Skip to the start of the next term
+            while chars
+                .next_if(|c| c.is_whitespace())
+                .is_some()
+            {}
+            if chars.peek().is_none() {
+                break;
+            }
+            let negated = chars.next_if_eq(&'-').is_some();
+            // Read a field name or unquoted text
+            let mut word = String::new();
+            while let Some(c) = chars.next_if(|c| {
+                !c.is_whitespace() && *c != ':' && *c != '"'
+            }) {
+                word.push(c);
+            }
+            let field = if chars.next_if_eq(&':').is_some()
+            {
+                let field = Field::ALL
+                    .into_iter()
+                    .find(|field| field.name() == word)
+                    .ok_or_else(|| {
+                        let fields = Field::ALL
+                            .map(|field| {
+                                format!("'{}'", field.name())
+                            })
+                            .join(", ");
+                        invalid(format!(
+                            "unknown field '{word}', expected one of {fields}"
+                        ))
+                    })?;
+                word.clear();
+                Some(field)
+            } else {
+                None
+            };
+            // Read the rest of the text, which may be quoted
+            let exact = chars.next_if_eq(&'"').is_some();
+            if exact {
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => word.push(c),
+                        None => {
+                            return Err(invalid(
+                                "unterminated '\"'".into(),
+                            ))
+                        }
+                    }
+                }
+            } else {
+                while let Some(c) =
+                    chars.next_if(|c| !c.is_whitespace())
+                {
+                    word.push(c);
+                }
+            }
+            if word.is_empty() {
+                match field {
+                    Some(field) => {
+                        return Err(invalid(format!(
+                            "expected text after '{}:'",
+                            field.name()
+                        )))
+                    }
+                    // A lone '-' or '""' matches everything
+                    None => continue,
+                }
+            }
+            terms.push(Term {
+                field,
+                text: word,
+                negated,
+                exact,
+            });
+        }
+        Ok(Self { terms })
+    }
+    /// How relevant an item is, or `None` if it doesn't
+    /// satisfy every term.
+    pub fn score(
+        &self,
+        item: &impl Searchable,
+    ) -> Option<u32> {
+        let mut total = 0;
+        for term in &self.terms {
+            match (term.score(item), term.negated) {
+                (Some(_), true) | (None, false) => {
+                    return None
+                }
+                (Some(score), false) => total += score,
+                (None, true) => {}
+            }
+        }
+        Some(total)
+    }
+    /// Whether an item satisfies every term.
+    pub fn matches(&self, item: &impl Searchable) -> bool {
+        self.score(item).is_some()
+    }
+    /// The items that match, best first, keeping their
+    /// order when they score the same.
+    pub fn rank<'a, T: Searchable>(
+        &self,
+        items: &'a [T],
+    ) -> Vec<&'a T> {
+        let mut ranked = items
+            .iter()
+            .filter_map(|item| {
+                Some((self.score(item)?, item))
+            })
+            .collect::<Vec<_>>();
+        ranked.sort_by_key(|(score, _)| {
+            std::cmp::Reverse(*score)
+        });
+        ranked.into_iter().map(|(_, item)| item).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn substring_prefers_earlier_matches() {
+        assert_eq!(substring("cli", "cli"), Some(EXACT));
+        assert_eq!(substring("cli", "client"), Some(800));
+        assert_eq!(
+            substring("cli", "maddi-cli"),
+            Some(600)
+        );
+        assert_eq!(substring("cli", "server"), None);
+    }
+
+    #[test]
+    fn fuzzy_tolerates_gaps_and_typos() {
+        assert_eq!(fuzzy("gm", "git-manager"), Some(370));
+        assert_eq!(
+            fuzzy("managre", "git-manager"),
+            Some(150)
+        );
+        assert_eq!(fuzzy("xyz", "git-manager"), None);
+    }
+
+    #[test]
+    fn fuzzy_long_queries_with_many_gaps() {
+        let text = "abcdefghijklmnopqrstuv";
+        let value = text
+            .chars()
+            .map(|c| format!("{c}--"))
+            .collect::<String>();
+        assert_eq!(fuzzy(text, &value), Some(210));
+    }
+
+    #[test]
+    fn distance_counts_edits_and_swaps() {
+        let chars =
+            |text: &str| text.chars().collect::<Vec<_>>();
+        let distance = |a: &str, b: &str| {
+            distance(&chars(a), &chars(b))
+        };
+        assert_eq!(distance("", ""), 0);
+        assert_eq!(distance("abc", ""), 3);
+        assert_eq!(distance("abc", "abc"), 0);
+        assert_eq!(distance("abc", "abd"), 1);
+        assert_eq!(distance("abc", "acb"), 1);
+        assert_eq!(distance("abc", "abcd"), 1);
+        assert_eq!(distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn parse_fields_negation_and_quotes() {
+        let query = Query::parse(
+            r#"tag:rust -name:cli "exact phrase" plain"#,
+        )
+        .ok()
+        .expect("query should parse");
+        let term =
+            |field, text: &str, negated, exact| Term {
+                field,
+                text: text.into(),
+                negated,
+                exact,
+            };
+        assert_eq!(
+            query.terms,
+            [
+                term(
+                    Some(Field::Tag),
+                    "rust",
+                    false,
+                    false
+                ),
+                term(Some(Field::Name), "cli", true, false),
+                term(None, "exact phrase", false, true),
+                term(None, "plain", false, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_empty_terms_match_everything() {
+        assert_eq!(
+            Query::parse("").ok(),
+            Some(Query::default())
+        );
+        assert_eq!(
+            Query::parse(r#" - "" "#).ok(),
+            Some(Query::default())
+        );
+    }
+
+    #[test]
+    fn parse_rejects_bad_queries() {
+        assert!(Query::parse("colour:red").is_err());
+        assert!(Query::parse(r#""unterminated"#).is_err());
+        assert!(Query::parse("tag:").is_err());
+    }
+}
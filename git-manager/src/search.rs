@@ -0,0 +1,261 @@
+// SPDX-FileCopyrightText: 2025 Madeline Baggins <declanbaggins@gmail.com>
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+use crate::{
+    cfg::{Config, Repository},
+    plan::json_string,
+};
+
+/// How search results are printed.
+#[derive(clap::ValueEnum, Clone, Copy)]
+pub enum Format {
+    /// A `<results>` document of `<git>` elements
+    Xml,
+    /// A JSON array of objects
+    Json,
+    /// The name and path of each result, tab separated
+    Plain,
+}
+
+/// Renders the repositories that matched a search as a
+/// single document ending in a newline.
+pub fn render(
+    config: &Config,
+    results: &[&Repository],
+    format: Format,
+) -> String {
+    let mut rendered = String::new();
+    match format {
+        Format::Xml => {
+            rendered.push_str("<results>\n");
+            for repository in results {
+                rendered.push_str(&xml(config, repository));
+            }
+            rendered.push_str("</results>\n");
+        }
+        Format::Json => {
+            let results = results
+                .iter()
+                .map(|repository| json(config, repository))
+                .collect::<Vec<_>>();
+            rendered = format!("[{}]\n", results.join(","));
+        }
+        Format::Plain => {
+            for repository in results {
+                let name = repository.name();
+                let path = config.store.join(name);
+                rendered.push_str(&format!(
+                    "{name}\t{}\n",
+                    path.display()
+                ));
+            }
+        }
+    }
+    rendered
+}
+
+/// Renders a result as a `<git>` element.
+fn xml(config: &Config, repository: &Repository) -> String {
+    let element = |name: &str, value: &str| {
+        format!(
+            "    <{name}>{}</{name}>\n",
+            xml_escape(value)
+        )
+    };
+    let name = repository.name();
+    let mut rendered = String::from("  <git>\n");
+    rendered.push_str(&element("alias", name));
+    rendered.push_str(&element(
+        "path",
+        &config.store.join(name).display().to_string(),
+    ));
+    for tag in repository.tags() {
+        rendered.push_str(&element("tag", tag));
+    }
+    for link in repository.symlinks(&config.symlinks) {
+        rendered.push_str(&element(
+            "symlink",
+            &link.display().to_string(),
+        ));
+    }
+    for (field, value) in repository.metadata().fields() {
+        rendered.push_str(&element(field, value));
+    }
+    rendered.push_str("  </git>\n");
+    rendered
+}
+
+/// Renders a result as a JSON object.
+fn json(
+    config: &Config,
+    repository: &Repository,
+) -> String {
+    let list = |items: Vec<String>| {
+        let items = items
+            .iter()
+            .map(|item| json_string(item))
+            .collect::<Vec<_>>();
+        format!("[{}]", items.join(","))
+    };
+    let name = repository.name();
+    let mut fields = vec![
+        format!(r#""name":{}"#, json_string(name)),
+        format!(
+            r#""path":{}"#,
+            json_string(
+                &config
+                    .store
+                    .join(name)
+                    .display()
+                    .to_string()
+            )
+        ),
+        format!(
+            r#""tags":{}"#,
+            list(repository.tags().to_vec())
+        ),
+        format!(
+            r#""symlinks":{}"#,
+            list(
+                repository
+                    .symlinks(&config.symlinks)
+                    .map(|link| link.display().to_string())
+                    .collect()
+            )
+        ),
+    ];
+    for (field, value) in repository.metadata().fields() {
+        fields.push(format!(
+            r#""{field}":{}"#,
+            json_string(value)
+        ));
+    }
+    format!("{{{}}}", fields.join(","))
+}
+
+/// Escapes text for use inside an XML element.
+pub fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Reverses `xml_escape`, since maddi_xml leaves entities
+/// as they are.
+pub fn xml_unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        unescaped.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entities = [
+            ("&amp;", '&'),
+            ("&lt;", '<'),
+            ("&gt;", '>'),
+            ("&quot;", '"'),
+            ("&apos;", '\''),
+        ];
+        let entity = entities
+            .into_iter()
+            .find(|(entity, _)| rest.starts_with(entity));
+        match entity {
+            Some((entity, c)) => {
+                unescaped.push(c);
+                rest = &rest[entity.len()..];
+            }
+            None => {
+                unescaped.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    #[test]
+    fn escape_round_trips() {
+        let texts = [
+            "",
+            "plain",
+            "r&d",
+            "<tag attr=\"value\">",
+            "it's & it isn't",
+            "&amp; already escaped",
+            "&unknown; & lone",
+        ];
+        for text in texts {
+            assert_eq!(
+                xml_unescape(&xml_escape(text)),
+                text
+            );
+        }
+        assert_eq!(
+            xml_escape("a<b>&'\""),
+            "a&lt;b&gt;&amp;&apos;&quot;"
+        );
+        assert_eq!(
+            xml_unescape("&lt;&gt;&amp;amp;"),
+            "<>&amp;"
+        );
+    }
+
+    #[test]
+    fn render_escapes_config_text_once() {
+        let config = Config::parse(
+            Path::new("config.xml"),
+            r#"<config>
+                <branch>main</branch>
+                <store>/srv/store</store>
+                <symlinks>/srv/links</symlinks>
+                <repo name="r&amp;d">
+                    <symlink>x&amp;y</symlink>
+                    <tag>a&lt;b</tag>
+                    <description>&quot;Notes&quot; &amp; things</description>
+                </repo>
+            </config>"#,
+        )
+        .ok()
+        .expect("config should parse");
+        let results =
+            config.repositories.iter().collect::<Vec<_>>();
+        assert_eq!(
+            render(&config, &results, Format::Xml),
+            "<results>
+  <git>
+    <alias>r&amp;d</alias>
+    <path>/srv/store/r&amp;d</path>
+    <tag>a&lt;b</tag>
+    <symlink>/srv/links/x&amp;y</symlink>
+    <description>&quot;Notes&quot; &amp; things</description>
+  </git>
+</results>
+"
+        );
+        assert_eq!(
+            render(&config, &results, Format::Json),
+            r#"[{"name":"r&d","path":"/srv/store/r&d","tags":["a<b"],"symlinks":["/srv/links/x&y"],"description":"\"Notes\" & things"}]
+"#
+        );
+        assert_eq!(
+            render(&config, &results, Format::Plain),
+            "r&d\t/srv/store/r&d\n"
+        );
+    }
+}
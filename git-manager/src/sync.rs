@@ -0,0 +1,183 @@
+// SPDX-FileCopyrightText: 2025 Madeline Baggins <declanbaggins@gmail.com>
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+use std::path::Path;
+
+use crate::{
+    cfg::UPSTREAM_REMOTE, error::CommandExt, plan::git,
+    Error, ResultExt,
+};
+
+/// What happened to a branch when syncing it with upstream.
+pub enum Outcome {
+    /// The branch already matched upstream
+    UpToDate,
+    /// The branch didn't exist and was created
+    Created,
+    /// The branch was fast-forwarded to match upstream
+    FastForwarded { from: String, to: String },
+    /// The branch has commits upstream doesn't
+    Ahead,
+    /// The branch and upstream both have their own commits
+    Diverged,
+    /// Upstream doesn't have the branch
+    Missing,
+}
+
+impl Outcome {
+    /// Whether the branch is left out of sync.
+    pub fn failed(&self) -> bool {
+        matches!(self, Outcome::Diverged | Outcome::Missing)
+    }
+}
+
+impl std::fmt::Display for Outcome {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        match self {
+            Outcome::UpToDate => write!(f, "up to date"),
+            Outcome::Created => write!(f, "created"),
+            Outcome::FastForwarded { from, to } => {
+                let short = |id: &str| {
+                    id.get(..7).unwrap_or(id).to_string()
+                };
+                write!(
+                    f,
+                    "fast-forwarded {}..{}",
+                    short(from),
+                    short(to)
+                )
+            }
+            Outcome::Ahead => {
+                write!(f, "ahead of upstream, left alone")
+            }
+            Outcome::Diverged => {
+                write!(
+                    f,
+                    "diverged from upstream, left alone"
+                )
+            }
+            Outcome::Missing => {
+                write!(f, "missing upstream")
+            }
+        }
+    }
+}
+
+/// Fetches from upstream and fast-forwards each branch
+/// that can be, without ever overwriting local commits.
+pub fn sync(
+    repository: &Path,
+    branches: &[String],
+) -> Result<Vec<(String, Outcome)>, Error> {
+    git(repository)
+        .args(["fetch", "--prune", UPSTREAM_REMOTE])
+        .run()?;
+    let mut outcomes = vec![];
+    for branch in branches {
+        let local_ref = format!("refs/heads/{branch}");
+        let upstream_ref = format!(
+            "refs/remotes/{UPSTREAM_REMOTE}/{branch}"
+        );
+        let local = rev_parse(repository, &local_ref)?;
+        let upstream =
+            rev_parse(repository, &upstream_ref)?;
+        let outcome = match (local, upstream) {
+            (_, None) => Outcome::Missing,
+            (None, Some(upstream)) => {
+                git(repository)
+                    .args([
+                        "update-ref",
+                        &local_ref,
+                        &upstream,
+                    ])
+                    .run()?;
+                Outcome::Created
+            }
+            (Some(local), Some(upstream))
+                if local == upstream =>
+            {
+                Outcome::UpToDate
+            }
+            (Some(local), Some(upstream))
+                if is_ancestor(
+                    repository, &local, &upstream,
+                )? =>
+            {
+                git(repository)
+                    .args([
+                        "update-ref",
+                        &local_ref,
+                        &upstream,
+                        &local,
+                    ])
+                    .run()?;
+                Outcome::FastForwarded {
+                    from: local,
+                    to: upstream,
+                }
+            }
+            (Some(local), Some(upstream))
+                if is_ancestor(
+                    repository, &upstream, &local,
+                )? =>
+            {
+                Outcome::Ahead
+            }
+            _ => Outcome::Diverged,
+        };
+        outcomes.push((branch.clone(), outcome));
+    }
+    Ok(outcomes)
+}
+
+/// The commit a ref points to, if it exists.
+fn rev_parse(
+    repository: &Path,
+    reference: &str,
+) -> Result<Option<String>, Error> {
+    let mut cmd = git(repository);
+    cmd.args([
+        "rev-parse",
+        "--verify",
+        "--quiet",
+        reference,
+    ]);
+    let output = cmd.output().with(&cmd)?;
+    Ok(output.status.success().then(|| {
+        String::from_utf8_lossy(&output.stdout)
+            .trim()
+            .into()
+    }))
+}
+
+/// Whether `ancestor` is an ancestor of `commit`.
+fn is_ancestor(
+    repository: &Path,
+    ancestor: &str,
+    commit: &str,
+) -> Result<bool, Error> {
+    let mut cmd = git(repository);
+    cmd.args([
+        "merge-base",
+        "--is-ancestor",
+        ancestor,
+        commit,
+    ]);
+    let output = cmd.output().with(&cmd)?;
+    match output.status.code() {
+        Some(0) => Ok(true),
+        Some(1) => Ok(false),
+        _ => Err(Error::new(
+            "Command failed",
+            format!(
+                "{cmd:?}\n\t{}",
+                String::from_utf8_lossy(&output.stderr)
+                    .trim()
+            ),
+        )),
+    }
+}
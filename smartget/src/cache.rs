@@ -0,0 +1,92 @@
+// SPDX-FileCopyrightText: 2025 Madeline Baggins <declanbaggins@gmail.com>
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+use std::{
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use crate::repository::Repository;
+
+/// Each remote's repositories from the last time it was
+/// reached, kept in `$XDG_CACHE_HOME/smartget`.
+pub struct Cache {
+    directory: PathBuf,
+}
+
+impl Cache {
+    /// The cache in the user's cache directory, if they
+    /// have one.
+    pub fn new() -> Option<Self> {
+        let directory = std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .filter(|directory| directory.is_absolute())
+            .or_else(|| {
+                let home = std::env::var_os("HOME")?;
+                Some(PathBuf::from(home).join(".cache"))
+            })?;
+        Some(Self {
+            directory: directory.join("smartget"),
+        })
+    }
+    /// Where a remote's repositories are kept, named after
+    /// a hash of its key.
+    fn path(&self, key: &str) -> PathBuf {
+        // FNV-1a, which won't change between builds
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for byte in key.bytes() {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        self.directory.join(format!("{hash:016x}.xml"))
+    }
+    /// A remote's cached repositories and how long ago
+    /// they were cached.
+    pub fn load(
+        &self,
+        key: &str,
+    ) -> Option<(Vec<Repository>, Duration)> {
+        let path = self.path(key);
+        let modified =
+            path.metadata().ok()?.modified().ok()?;
+        let age = SystemTime::now()
+            .duration_since(modified)
+            .unwrap_or_default();
+        let source = std::fs::read_to_string(&path).ok()?;
+        let repositories =
+            Repository::parse_results(&source).ok()?;
+        Some((repositories, age))
+    }
+    /// Replaces a remote's cached repositories.
+    pub fn store(
+        &self,
+        key: &str,
+        repositories: &[Repository],
+    ) -> std::io::Result<()> {
+        std::fs::create_dir_all(&self.directory)?;
+        // Write a copy first so readers never see half a
+        // file
+        let path = self.path(key);
+        let copy = path.with_extension(format!(
+            "{}.tmp",
+            std::process::id()
+        ));
+        std::fs::write(
+            &copy,
+            Repository::render_results(repositories),
+        )?;
+        std::fs::rename(&copy, &path)
+    }
+}
+
+/// Roughly how long ago something happened, like `3h`.
+pub fn age(age: Duration) -> String {
+    let seconds = age.as_secs();
+    match seconds {
+        0..60 => format!("{seconds}s"),
+        60..3600 => format!("{}m", seconds / 60),
+        3600..86400 => format!("{}h", seconds / 3600),
+        _ => format!("{}d", seconds / 86400),
+    }
+}
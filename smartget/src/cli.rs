@@ -0,0 +1,57 @@
+// SPDX-FileCopyrightText: 2025 Madeline Baggins <declanbaggins@gmail.com>
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+use std::path::PathBuf;
+
+#[derive(clap::Parser)]
+pub struct Args {
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+    #[command(subcommand)]
+    pub command: Option<Commands>,
+    /// What to search for, like `tag:rust -tag:archived`
+    #[arg(default_value = "", allow_hyphen_values = true)]
+    pub search: String,
+    /// Print every result instead of picking one
+    #[arg(long)]
+    pub all: bool,
+}
+
+#[derive(clap::Subcommand)]
+pub enum Commands {
+    /// Clone the best match for a search
+    Get {
+        #[arg(allow_hyphen_values = true)]
+        search: String,
+        /// The directory to clone into, instead of the
+        /// config's `<directory>`
+        #[arg(long)]
+        directory: Option<PathBuf>,
+    },
+    /// Ask every remote for its repositories again,
+    /// replacing the cache
+    Refresh,
+}
+
+impl Args {
+    pub fn config(&self) -> Option<PathBuf> {
+        // Get the home directory, if there is one
+        let home =
+            std::env::var_os("HOME").map(PathBuf::from);
+        let file = |path| {
+            let file = match &home {
+                Some(home) => home.join(path),
+                None => PathBuf::from(path),
+            };
+            (file.is_absolute() && file.exists())
+                .then_some(file)
+        };
+        self.config
+            .clone()
+            .or(file(".smartget.xml"))
+            .or(file(".config/smartget.xml"))
+            .or(file(".config/smartget/config.xml"))
+            .or(file("/etc/smartget.xml"))
+    }
+}
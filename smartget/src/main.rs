@@ -2,134 +2,389 @@
 //
 // SPDX-License-Identifier: GPL-3.0-only
 
-use std::{path::PathBuf, process::Command};
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
 
-use maddi_xml::{Element, FromElement, Parser, Result};
+use maddi_git_manager::{
+    error::ResultExt, query::Query, Error,
+};
+use maddi_xml::{
+    self as xml, Content, FromElement, Parser,
+};
 
-#[derive(clap::Parser)]
-struct Args {
-    #[arg(long)]
-    config: Option<PathBuf>,
-    #[arg(default_value = "")]
-    search: String,
-}
+use cache::Cache;
+use remote::{Local, Remote, RemoteError, Scp, Ssh};
+use repository::Repository;
 
-impl Args {
-    pub fn config(&self) -> Option<PathBuf> {
-        // Get the home directory
-        let home: PathBuf =
-            std::env::var("HOME").unwrap().into();
-        let file = |path| {
-            let file = home.join(path);
-            file.exists().then_some(file)
-        };
-        self.config
-            .clone()
-            .or(file(".smartget.xml"))
-            .or(file(".config/smartget.xml"))
-            .or(file(".config/smartget/config.xml"))
-            .or(file("/etc/smartget.xml"))
-    }
-}
+mod cache;
+mod cli;
+mod picker;
+mod remote;
+mod repository;
 
 struct Config {
     remotes: Vec<Box<dyn Remote>>,
+    /// Where `get` clones repositories to
+    directory: Option<PathBuf>,
+    /// How long to wait for each remote
+    timeout: Duration,
+    /// Where remotes' repositories are kept between runs
+    cache: Option<Cache>,
+    /// How long cached repositories are used before asking
+    /// the remote again
+    cache_ttl: Duration,
 }
 
-trait Remote {
-    fn search(&self, search: &str) -> Vec<String>;
+impl Config {
+    fn parse(
+        path: &Path,
+        source: &str,
+    ) -> Result<Self, Error> {
+        let mut parser = Parser::new(path, source);
+        let mut config = Config {
+            remotes: Vec::new(),
+            directory: None,
+            timeout: Duration::from_secs(10),
+            cache: Cache::new(),
+            cache_ttl: Duration::from_secs(60 * 60),
+        };
+        while let Some(content) =
+            parser.parse::<Option<xml::Result<Content>>>()
+        {
+            let element = match content? {
+                Content::Element(element) => element,
+                Content::Text(_) => {
+                    return Err(parser
+                        .position
+                        .error("expected element".into())
+                        .into())
+                }
+            };
+            match element.name {
+                "ssh" => config
+                    .remotes
+                    .push(Box::new(Ssh::from_element(&element)?)),
+                "scp" => config
+                    .remotes
+                    .push(Box::new(Scp::from_element(&element)?)),
+                "local" => config
+                    .remotes
+                    .push(Box::new(Local::from_element(&element)?)),
+                "git" => config.remotes.push(Box::new(
+                    Repository::from_element(&element)?,
+                )),
+                "directory" => {
+                    config.directory =
+                        Some(PathBuf::from_element(&element)?)
+                }
+                "timeout" => {
+                    config.timeout = Duration::from_secs(
+                        u64::from_element(&element)?,
+                    )
+                }
+                "cache-ttl" => {
+                    config.cache_ttl = Duration::from_secs(
+                        u64::from_element(&element)?,
+                    )
+                }
+                _ => {
+                    return Err(element
+                        .position
+                        .error(
+                            "expected 'ssh', 'scp', 'local', 'git', 'directory', 'timeout', or 'cache-ttl' element"
+                                .into(),
+                        )
+                        .into())
+                }
+            }
+        }
+        Ok(config)
+    }
+    /// Searches every remote at once, best match first,
+    /// reporting any that fail along the way.
+    fn search(
+        &self,
+        search: &str,
+    ) -> Result<Search, Error> {
+        let query = Query::parse(search)?;
+        let search = self.repositories(false);
+        Ok(Search {
+            results: query
+                .rank(&search.results)
+                .into_iter()
+                .cloned()
+                .collect(),
+            ..search
+        })
+    }
+    /// Every remote's repositories, fetched at once and
+    /// reporting any remote that fails along the way.
+    /// `refresh` ignores anything already cached.
+    fn repositories(&self, refresh: bool) -> Search {
+        // Collect results from each remote as they come in
+        let (sender, receiver) = mpsc::channel();
+        let mut results = vec![];
+        let mut failed = 0;
+        thread::scope(|scope| {
+            for remote in &self.remotes {
+                let sender = sender.clone();
+                scope.spawn(move || {
+                    let _ = sender.send((
+                        remote.name(),
+                        self.fetch(
+                            remote.as_ref(),
+                            refresh,
+                        ),
+                    ));
+                });
+            }
+            drop(sender);
+            for (name, result) in receiver {
+                match result {
+                    Ok(found) => results.extend(found),
+                    Err(err) => {
+                        report(&name, &err);
+                        failed += 1;
+                    }
+                }
+            }
+        });
+        Search {
+            results,
+            failed,
+            searched: self.remotes.len(),
+        }
+    }
+    /// A remote's repositories, taken from the cache while
+    /// they're fresh, and from an old cache if the remote
+    /// can't be reached.
+    fn fetch(
+        &self,
+        remote: &dyn Remote,
+        refresh: bool,
+    ) -> Result<Vec<Repository>, RemoteError> {
+        let cache =
+            self.cache.as_ref().zip(remote.cache_key());
+        let cached = cache
+            .as_ref()
+            .and_then(|(cache, key)| cache.load(key));
+        if let Some((repositories, age)) = &cached {
+            if !refresh && *age < self.cache_ttl {
+                return Ok(repositories.clone());
+            }
+        }
+        match remote.repositories(self.timeout) {
+            Ok(repositories) => {
+                if let Some((cache, key)) = &cache {
+                    if let Err(err) =
+                        cache.store(key, &repositories)
+                    {
+                        warn(
+                            &remote.name(),
+                            format!("couldn't cache repositories: {err}"),
+                        );
+                    }
+                }
+                Ok(repositories)
+            }
+            Err(err) => match cached {
+                Some((repositories, age)) if !refresh => {
+                    warn(
+                        &remote.name(),
+                        format!(
+                            "{err}\n\tusing repositories cached {} ago",
+                            cache::age(age)
+                        ),
+                    );
+                    Ok(repositories
+                        .into_iter()
+                        .map(|repository| Repository {
+                            stale: true,
+                            ..repository
+                        })
+                        .collect())
+                }
+                _ => Err(err),
+            },
+        }
+    }
 }
 
-struct Ssh {
-    remote: Option<String>,
-    command: String,
-    args: Vec<String>,
+/// The repositories found on every remote.
+struct Search {
+    /// The results, best match first
+    results: Vec<Repository>,
+    /// How many remotes couldn't be searched
+    failed: usize,
+    /// How many remotes there are
+    searched: usize,
 }
 
-impl<'a, 'b> FromElement<'a, 'b> for Ssh {
-    fn from_element(
-        element: &'b Element<'a>,
-    ) -> Result<'a, Self> {
-        Ok(Self {
-            remote: element.optional_child("remote")?,
-            command: element.child("command")?,
-            args: element
-                .children("arg")
-                .collect::<Result<_>>()?,
-        })
+impl Search {
+    /// Fails if any remote couldn't be searched, so
+    /// smartget can exit unsuccessfully after using the
+    /// results it did get.
+    fn complete(&self) -> Result<(), Error> {
+        if self.failed == 0 {
+            return Ok(());
+        }
+        Err(Error::new(
+            "Search incomplete",
+            format!(
+                "{} of {} remotes couldn't be searched",
+                self.failed, self.searched
+            ),
+        ))
     }
 }
 
-impl Remote for Ssh {
-    fn search(&self, search: &str) -> Vec<String> {
-        let mut command = self.command.clone();
-        for arg in &self.args {
-            command.push(' ');
-            command.push_str(arg);
-        }
-        command.push_str(" '");
-        command.push_str(search);
-        command.push('\'');
-        String::from_utf8_lossy(
-            &Command::new("ssh")
-                .arg(self.remote.as_ref().unwrap())
-                .arg("-t")
-                .arg(command)
-                .output()
-                .unwrap()
-                .stdout,
-        )
-        .lines()
-        .map(|line| line.into())
-        .collect()
-    }
+/// Reports a remote that couldn't be searched without
+/// holding up the others.
+fn report(remote: &str, err: &RemoteError) {
+    eprintln!("{RED}error{DEFAULT}: {remote}: {err}");
+}
+
+/// Reports a problem with a remote that smartget could
+/// work around.
+fn warn(remote: &str, detail: impl std::fmt::Display) {
+    eprintln!(
+        "{YELLOW}warning{DEFAULT}: {remote}: {detail}"
+    );
 }
 
 const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
 const DEFAULT: &str = "\x1b[1;39m";
 
 fn main() {
     // Get the arguments
-    let args: Args = clap::Parser::parse();
+    let args: cli::Args = clap::Parser::parse();
     // Load the configuration file
     let Some(config_path) = args.config() else {
-        println!(
+        eprintln!(
             "{RED}error{DEFAULT}: could not find any configuration file"
         );
-        return;
+        std::process::exit(1);
     };
-    // Read in the configuration file
-    let config_string =
-        std::fs::read_to_string(&config_path).unwrap();
-    // Parse the config
-    let mut parser =
-        Parser::new(&config_path, &config_string);
-    let mut config = Config {
-        remotes: Vec::new(),
-    };
-    while let Some(element) =
-        parser.parse::<Option<Result<Element>>>()
-    {
-        match element {
-            Ok(e) => match Ssh::from_element(&e) {
-                Ok(s) => config.remotes.push(Box::new(s)),
-                Err(e) => {
-                    println!("{e}");
-                    return;
-                }
-            },
-            Err(e) => {
-                println!("{e}");
-                return;
-            }
+    // Read in the configuration file and run the command,
+    // printing out any errors
+    let result = std::fs::read_to_string(&config_path)
+        .with(config_path.as_path())
+        .map_err(Error::from)
+        .and_then(|source| {
+            Config::parse(&config_path, &source)
+        })
+        .and_then(|config| run(args, &config));
+    if let Err(err) = result {
+        eprintln!("{err}");
+        std::process::exit(1);
+    }
+}
+
+fn run(
+    args: cli::Args,
+    config: &Config,
+) -> Result<(), Error> {
+    match args.command {
+        Some(cli::Commands::Get {
+            ref search,
+            ref directory,
+        }) => {
+            handle_get(config, search, directory.as_deref())
+        }
+        Some(cli::Commands::Refresh) => {
+            handle_refresh(config)
+        }
+        None => {
+            handle_search(config, &args.search, args.all)
         }
     }
-    // Search all the endpoints
-    let results =
-        config.remotes.iter().flat_map(move |remote| {
-            remote.search(&args.search)
-        });
-    for result in results {
-        println!("{result}");
+}
+
+fn handle_refresh(config: &Config) -> Result<(), Error> {
+    let search = config.repositories(true);
+    println!(
+        "found {} repositories on {} of {} remotes",
+        search.results.len(),
+        search.searched - search.failed,
+        search.searched
+    );
+    search.complete()
+}
+
+fn handle_search(
+    config: &Config,
+    search: &str,
+    all: bool,
+) -> Result<(), Error> {
+    let search = config.search(search)?;
+    let results = &search.results;
+    // Let the user choose when there's someone to ask
+    if !all && results.len() > 1 && interactive() {
+        if let Some(repository) = picker::pick(results)? {
+            println!("{}", repository.url());
+        }
+    } else {
+        for result in results {
+            println!("{result}");
+        }
+    }
+    search.complete()
+}
+
+/// Whether the user can pick results in the terminal.
+fn interactive() -> bool {
+    use std::io::IsTerminal;
+    std::io::stdout().is_terminal()
+        && std::io::stdin().is_terminal()
+}
+
+fn handle_get(
+    config: &Config,
+    search: &str,
+    directory: Option<&Path>,
+) -> Result<(), Error> {
+    // Find the best match
+    let found = config.search(search)?;
+    let repository = match found.results.as_slice() {
+        [] => {
+            // It may be on a remote that failed
+            found.complete()?;
+            return Err(Error::new(
+                "No match",
+                format!("nothing matched '{search}'"),
+            ));
+        }
+        [_, _, ..] if interactive() => {
+            match picker::pick(&found.results)? {
+                Some(repository) => repository,
+                None => return found.complete(),
+            }
+        }
+        [best, ..] => best,
+    };
+    // Clone it into the directory
+    let directory = directory
+        .or(config.directory.as_deref())
+        .unwrap_or(Path::new("."));
+    let destination = directory.join(&repository.alias);
+    let mut clone = Command::new("git");
+    clone
+        .arg("clone")
+        .arg(repository.url())
+        .arg(&destination);
+    let status = clone.status().with(&clone)?;
+    if !status.success() {
+        return Err(Error::new(
+            "Clone failed",
+            format!("{clone:?} exited with {status}"),
+        ));
     }
+    println!("{}", destination.display());
+    found.complete()
 }
@@ -0,0 +1,280 @@
+// SPDX-FileCopyrightText: 2025 Madeline Baggins <declanbaggins@gmail.com>
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+use std::{
+    fs::File,
+    io::{Read, Write},
+    process::{Command, Stdio},
+};
+
+use maddi_git_manager::{
+    error::{CommandExt, ResultExt},
+    query::Query,
+    Error,
+};
+
+use crate::repository::Repository;
+
+/// The number of lines used to preview the selection.
+const PREVIEW: usize = 4;
+
+/// A key the picker understands.
+enum Key {
+    Char(char),
+    Backspace,
+    Clear,
+    Up,
+    Down,
+    Enter,
+    Cancel,
+    /// A key the picker doesn't use
+    Other,
+}
+
+/// Puts the terminal in raw mode until dropped.
+struct Raw {
+    tty: File,
+    saved: String,
+}
+
+impl Raw {
+    fn new() -> Result<Self, Error> {
+        let tty = std::path::Path::new("/dev/tty");
+        let tty = File::options()
+            .read(true)
+            .write(true)
+            .open(tty)
+            .with(tty)?;
+        let saved = stty(&tty, &["-g"])?;
+        // Reads time out after a tenth of a second so a
+        // lone escape can be told apart from arrow keys
+        stty(
+            &tty,
+            &["raw", "-echo", "min", "0", "time", "1"],
+        )?;
+        let mut raw = Self {
+            tty,
+            saved: saved.trim().into(),
+        };
+        // Draw on the alternate screen without a cursor
+        raw.write("\x1b[?1049h\x1b[?25l")?;
+        Ok(raw)
+    }
+    fn write(&mut self, text: &str) -> Result<(), Error> {
+        let tty = std::path::Path::new("/dev/tty");
+        self.tty.write_all(text.as_bytes()).with(tty)?;
+        Ok(self.tty.flush().with(tty)?)
+    }
+    /// The next byte typed, if one arrives in time.
+    fn byte(&mut self) -> Result<Option<u8>, Error> {
+        let mut byte = [0];
+        let read = self
+            .tty
+            .read(&mut byte)
+            .with(std::path::Path::new("/dev/tty"))?;
+        Ok((read == 1).then_some(byte[0]))
+    }
+    /// Waits for the next key.
+    fn key(&mut self) -> Result<Key, Error> {
+        let first = loop {
+            if let Some(byte) = self.byte()? {
+                break byte;
+            }
+        };
+        Ok(match first {
+            b'\r' | b'\n' => Key::Enter,
+            0x7f | 0x08 => Key::Backspace,
+            // Ctrl-U
+            0x15 => Key::Clear,
+            // Ctrl-P and Ctrl-N
+            0x10 => Key::Up,
+            0x0e => Key::Down,
+            0x1b => match self.byte()? {
+                Some(b'[' | b'O') => match self.byte()? {
+                    Some(b'A') => Key::Up,
+                    Some(b'B') => Key::Down,
+                    _ => Key::Other,
+                },
+                _ => Key::Cancel,
+            },
+            byte if byte < 0x20 => match byte {
+                // Ctrl-C, Ctrl-D and Ctrl-G
+                0x03 | 0x04 | 0x07 => Key::Cancel,
+                _ => Key::Other,
+            },
+            byte => {
+                // Collect the rest of a UTF-8 character
+                let mut bytes = vec![byte];
+                while std::str::from_utf8(&bytes).is_err()
+                    && bytes.len() < 4
+                {
+                    match self.byte()? {
+                        Some(byte) => bytes.push(byte),
+                        None => break,
+                    }
+                }
+                std::str::from_utf8(&bytes)
+                    .ok()
+                    .and_then(|text| text.chars().next())
+                    .map_or(Key::Other, Key::Char)
+            }
+        })
+    }
+    /// The terminal's height and width.
+    fn size(&self) -> (usize, usize) {
+        let size = stty(&self.tty, &["size"]).ok();
+        let mut size = size
+            .iter()
+            .flat_map(|size| size.split_whitespace())
+            .filter_map(|n| n.parse().ok());
+        match (size.next(), size.next()) {
+            (Some(rows), Some(cols)) if rows > 0 => {
+                (rows, cols)
+            }
+            _ => (24, 80),
+        }
+    }
+}
+
+impl Drop for Raw {
+    fn drop(&mut self) {
+        let _ = self.write("\x1b[?25h\x1b[?1049l");
+        let _ = stty(&self.tty, &[&self.saved]);
+    }
+}
+
+/// Runs `stty` against the terminal.
+fn stty(
+    tty: &File,
+    args: &[&str],
+) -> Result<String, Error> {
+    let tty = tty
+        .try_clone()
+        .with(std::path::Path::new("/dev/tty"))?;
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::from(tty))
+        .run()?;
+    Ok(String::from_utf8_lossy(&output.stdout).into())
+}
+
+/// Lets the user pick one of `results` with the arrow keys,
+/// narrowing them down by typing. Returns `None` if they
+/// cancel.
+pub fn pick(
+    results: &[Repository],
+) -> Result<Option<&Repository>, Error> {
+    let mut raw = Raw::new()?;
+    let mut filter = String::new();
+    let mut matches = results.iter().collect::<Vec<_>>();
+    let mut selected = 0;
+    let mut scroll = 0;
+    loop {
+        // Keep the selection on screen
+        let (rows, cols) = raw.size();
+        let height =
+            rows.saturating_sub(PREVIEW + 3).max(1);
+        selected =
+            selected.min(matches.len().saturating_sub(1));
+        if selected < scroll {
+            scroll = selected;
+        } else if selected >= scroll + height {
+            scroll = selected + 1 - height;
+        }
+        raw.write(&render(
+            &filter, &matches, selected, scroll, height,
+            cols,
+        ))?;
+        match raw.key()? {
+            Key::Enter => {
+                return Ok(matches.get(selected).copied())
+            }
+            Key::Cancel => return Ok(None),
+            Key::Up => {
+                selected = selected.saturating_sub(1)
+            }
+            Key::Down => selected += 1,
+            key => {
+                match key {
+                    Key::Other => continue,
+                    Key::Char(c) => filter.push(c),
+                    Key::Backspace => {
+                        filter.pop();
+                    }
+                    _ => filter.clear(),
+                }
+                // Keep the last matches while a quote is
+                // still being typed
+                if let Ok(query) = Query::parse(&filter) {
+                    matches = query.rank(results);
+                    selected = 0;
+                }
+            }
+        }
+    }
+}
+
+/// Draws a frame of the picker.
+fn render(
+    filter: &str,
+    matches: &[&Repository],
+    selected: usize,
+    scroll: usize,
+    height: usize,
+    cols: usize,
+) -> String {
+    let entry = |repository: &Repository| {
+        let stale = match repository.stale {
+            true => "  (stale)",
+            false => "",
+        };
+        format!(
+            "{}  {}{stale}",
+            repository.alias,
+            repository.url()
+        )
+    };
+    let line = |text: &str| {
+        let text =
+            text.chars().take(cols).collect::<String>();
+        format!("{text}\x1b[K\r\n")
+    };
+    let mut frame = String::from("\x1b[H");
+    frame.push_str(&line(&format!(
+        "> {filter}  ({}/{})",
+        matches.len().min(selected + 1),
+        matches.len()
+    )));
+    for row in scroll..scroll + height {
+        match matches.get(row) {
+            Some(repository) if row == selected => {
+                frame.push_str("\x1b[7m");
+                frame.push_str(&line(&entry(repository)));
+                frame.push_str("\x1b[0m");
+            }
+            Some(repository) => {
+                frame.push_str(&line(&entry(repository)))
+            }
+            None => frame.push_str(&line("")),
+        }
+    }
+    frame.push_str(&line(&"-".repeat(cols)));
+    // Preview the selected repository
+    let preview = matches.get(selected).map(|repository| {
+        [
+            repository.url(),
+            format!("tags: {}", repository.tags.join(" ")),
+            repository
+                .description
+                .clone()
+                .unwrap_or_default(),
+            repository.homepage.clone().unwrap_or_default(),
+        ]
+    });
+    for text in preview.unwrap_or_default() {
+        frame.push_str(&line(&text));
+    }
+    frame.push_str("\x1b[J");
+    frame
+}
@@ -0,0 +1,472 @@
+// SPDX-FileCopyrightText: 2025 Madeline Baggins <declanbaggins@gmail.com>
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+use std::{
+    io::Read,
+    os::unix::fs::DirBuilderExt,
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus, Output, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use maddi_git_manager::cfg;
+use maddi_xml::{Element, FromElement, Result};
+
+use crate::repository::Repository;
+
+/// Remotes are searched on their own threads, so they must
+/// be shareable.
+pub trait Remote: Sync {
+    /// What to call the remote when reporting problems.
+    fn name(&self) -> String;
+    /// What identifies the remote in the cache, or `None`
+    /// if it's quick enough to never need caching.
+    fn cache_key(&self) -> Option<String> {
+        None
+    }
+    /// Every repository the remote has, giving up on any
+    /// command that takes longer than `timeout`.
+    fn repositories(
+        &self,
+        timeout: Duration,
+    ) -> std::result::Result<Vec<Repository>, RemoteError>;
+}
+
+/// Why a remote couldn't be searched.
+#[derive(Debug)]
+pub enum RemoteError {
+    /// The config left out an element the remote needs
+    MissingField(&'static str),
+    /// A command couldn't be started
+    Spawn {
+        command: String,
+        error: std::io::Error,
+    },
+    /// A command was killed for taking too long
+    Timeout { command: String, after: Duration },
+    /// A command exited unsuccessfully
+    Exit {
+        command: String,
+        status: ExitStatus,
+        stderr: String,
+    },
+    /// A private directory to copy into couldn't be made
+    TempDir {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    /// A file the remote needs couldn't be read
+    Read {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    /// The remote's repositories couldn't be understood
+    Parse(String),
+}
+
+impl std::fmt::Display for RemoteError {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        match self {
+            RemoteError::MissingField(field) => {
+                write!(f, "missing <{field}> element")
+            }
+            RemoteError::Spawn { command, error } => {
+                write!(f, "couldn't run {command}: {error}")
+            }
+            RemoteError::Timeout { command, after } => {
+                write!(
+                    f,
+                    "gave up on {command} after {}s",
+                    after.as_secs_f32()
+                )
+            }
+            RemoteError::Exit {
+                command,
+                status,
+                stderr,
+            } => {
+                write!(
+                    f,
+                    "{command} exited with {status}"
+                )?;
+                match stderr.trim() {
+                    "" => Ok(()),
+                    stderr => write!(f, "\n\t{stderr}"),
+                }
+            }
+            RemoteError::TempDir { path, error } => {
+                write!(
+                    f,
+                    "couldn't create {}: {error}",
+                    path.display()
+                )
+            }
+            RemoteError::Read { path, error } => {
+                write!(
+                    f,
+                    "couldn't read {}: {error}",
+                    path.display()
+                )
+            }
+            RemoteError::Parse(detail) => {
+                write!(f, "invalid repositories: {detail}")
+            }
+        }
+    }
+}
+
+/// Runs a command to completion like `Command::output`,
+/// killing it if it runs longer than `timeout` and failing
+/// if it exits unsuccessfully.
+fn run(
+    command: &mut Command,
+    timeout: Duration,
+) -> std::result::Result<Output, RemoteError> {
+    let name = format!("{command:?}");
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|error| RemoteError::Spawn {
+            command: name.clone(),
+            error,
+        })?;
+    // Keep the pipes drained so the command can't block
+    // on a full one
+    let stdout = drain(child.stdout.take());
+    let stderr = drain(child.stderr.take());
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() < deadline => {
+                thread::sleep(Duration::from_millis(10))
+            }
+            // Don't leave it running if it can't be
+            // waited on either
+            _ => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(RemoteError::Timeout {
+                    command: name,
+                    after: timeout,
+                });
+            }
+        }
+    };
+    let output = Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    };
+    if !status.success() {
+        return Err(RemoteError::Exit {
+            command: name,
+            status,
+            stderr: String::from_utf8_lossy(&output.stderr)
+                .into(),
+        });
+    }
+    Ok(output)
+}
+
+/// Reads everything from a pipe on another thread.
+fn drain(
+    pipe: Option<impl Read + Send + 'static>,
+) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut read = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut read);
+        }
+        read
+    })
+}
+
+/// Every repository in a git-manager config file, which
+/// errors refer to as `name`.
+fn read_config(
+    path: &Path,
+    name: &Path,
+    remote: Option<&str>,
+) -> std::result::Result<Vec<Repository>, RemoteError> {
+    let source =
+        std::fs::read_to_string(path).map_err(|error| {
+            RemoteError::Read {
+                path: path.into(),
+                error,
+            }
+        })?;
+    let config = cfg::Config::parse(name, &source)
+        .map_err(|err| {
+            RemoteError::Parse(err.to_string())
+        })?;
+    Ok(Repository::from_config(&config, remote))
+}
+
+/// Quotes text so a POSIX shell reads it as one word,
+/// whatever it contains.
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+/// Runs `git-manager search` on a server over ssh. The
+/// `<command>` is run by the server's shell as written, and
+/// each `<arg>` is passed to it as is.
+pub struct Ssh {
+    remote: Option<String>,
+    command: String,
+    args: Vec<String>,
+}
+
+impl<'a, 'b> FromElement<'a, 'b> for Ssh {
+    fn from_element(
+        element: &'b Element<'a>,
+    ) -> Result<'a, Self> {
+        Ok(Self {
+            remote: element.optional_child("remote")?,
+            command: element.child("command")?,
+            args: element
+                .children("arg")
+                .collect::<Result<_>>()?,
+        })
+    }
+}
+
+impl Remote for Ssh {
+    fn name(&self) -> String {
+        self.remote.clone().unwrap_or_else(|| "ssh".into())
+    }
+    fn cache_key(&self) -> Option<String> {
+        let mut key = format!("ssh {}", self.name());
+        for part in
+            [&self.command].into_iter().chain(&self.args)
+        {
+            key.push(' ');
+            key.push_str(part);
+        }
+        Some(key)
+    }
+    fn repositories(
+        &self,
+        timeout: Duration,
+    ) -> std::result::Result<Vec<Repository>, RemoteError>
+    {
+        let remote = self
+            .remote
+            .as_ref()
+            .ok_or(RemoteError::MissingField("remote"))?;
+        // ssh hands the remote shell a single string, so
+        // only the command itself is left unquoted
+        let mut command = self.command.clone();
+        for arg in &self.args {
+            command.push(' ');
+            command.push_str(&shell_quote(arg));
+        }
+        let output = run(
+            Command::new("ssh").arg(remote).arg(command),
+            timeout,
+        )?;
+        let output =
+            String::from_utf8_lossy(&output.stdout);
+        // Results can be cloned from the same remote
+        let results = Repository::parse_results(&output)
+            .map_err(RemoteError::Parse)?;
+        Ok(results
+            .into_iter()
+            .map(|repository| Repository {
+                remote: Some(remote.clone()),
+                ..repository
+            })
+            .collect())
+    }
+}
+
+/// Copies a git-manager config.xml down from the server to
+/// read its repositories.
+pub struct Scp {
+    remote: Option<String>,
+    path: String,
+    command: String,
+    args: Vec<String>,
+}
+
+impl<'a, 'b> FromElement<'a, 'b> for Scp {
+    fn from_element(
+        element: &'b Element<'a>,
+    ) -> Result<'a, Self> {
+        Ok(Self {
+            remote: element.optional_child("remote")?,
+            path: element.child("path")?,
+            command: element
+                .optional_child("command")?
+                .unwrap_or_else(|| "scp".into()),
+            args: element
+                .children("arg")
+                .collect::<Result<_>>()?,
+        })
+    }
+}
+
+impl Remote for Scp {
+    fn name(&self) -> String {
+        match &self.remote {
+            Some(remote) => {
+                format!("{remote}:{}", self.path)
+            }
+            None => self.path.clone(),
+        }
+    }
+    fn cache_key(&self) -> Option<String> {
+        let mut key = format!("scp {}", self.command);
+        for arg in &self.args {
+            key.push(' ');
+            key.push_str(arg);
+        }
+        key.push(' ');
+        key.push_str(&self.name());
+        Some(key)
+    }
+    fn repositories(
+        &self,
+        timeout: Duration,
+    ) -> std::result::Result<Vec<Repository>, RemoteError>
+    {
+        // Copy the config into a directory only we can
+        // use, which fails rather than reusing one someone
+        // else planted
+        static COPIES: AtomicUsize = AtomicUsize::new(0);
+        let directory = std::env::temp_dir().join(format!(
+            "smartget-{}-{}",
+            std::process::id(),
+            COPIES.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::DirBuilder::new()
+            .mode(0o700)
+            .create(&directory)
+            .map_err(|error| RemoteError::TempDir {
+                path: directory.clone(),
+                error,
+            })?;
+        let copy = directory.join("config.xml");
+        let source = self.name();
+        let results = run(
+            Command::new(&self.command)
+                .args(&self.args)
+                .arg(&source)
+                .arg(&copy),
+            timeout,
+        )
+        .and_then(|_| {
+            read_config(
+                &copy,
+                Path::new(&source),
+                self.remote.as_deref(),
+            )
+        });
+        let _ = std::fs::remove_dir_all(&directory);
+        results
+    }
+}
+
+/// Reads the repositories in a git-manager config.xml on
+/// this machine.
+pub struct Local {
+    path: PathBuf,
+}
+
+impl<'a, 'b> FromElement<'a, 'b> for Local {
+    fn from_element(
+        element: &'b Element<'a>,
+    ) -> Result<'a, Self> {
+        Ok(Self {
+            path: element.child("path")?,
+        })
+    }
+}
+
+impl Remote for Local {
+    fn name(&self) -> String {
+        self.path.display().to_string()
+    }
+    fn repositories(
+        &self,
+        _timeout: Duration,
+    ) -> std::result::Result<Vec<Repository>, RemoteError>
+    {
+        read_config(&self.path, &self.path, None)
+    }
+}
+
+/// A `<git>` element always offers its own repository.
+impl Remote for Repository {
+    fn name(&self) -> String {
+        self.alias.clone()
+    }
+    fn repositories(
+        &self,
+        _timeout: Duration,
+    ) -> std::result::Result<Vec<Repository>, RemoteError>
+    {
+        Ok(vec![self.clone()])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What a POSIX shell reads a quoted word back as.
+    fn unquote(word: &str) -> String {
+        let script =
+            format!("printf %s {}", shell_quote(word));
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(script)
+            .output()
+            .expect("sh should run");
+        assert!(output.status.success());
+        String::from_utf8(output.stdout)
+            .expect("output should be UTF-8")
+    }
+
+    #[test]
+    fn shell_quote_round_trips_hostile_strings() {
+        let hostile = [
+            "",
+            "plain",
+            "'",
+            "''",
+            r"'\''",
+            "it's",
+            "'; touch /tmp/smartget-pwned; '",
+            "$(touch /tmp/smartget-pwned)",
+            "`touch /tmp/smartget-pwned`",
+            "${HOME}",
+            r"back\slash\",
+            "\"double\"",
+            "line\nbreak",
+            "tab\tand space",
+            "-rf *",
+            "; | & > < ! # ~",
+        ];
+        for word in hostile {
+            assert_eq!(unquote(word), word);
+        }
+        assert!(!Path::new("/tmp/smartget-pwned").exists());
+    }
+
+    #[test]
+    fn shell_quote_makes_one_word() {
+        assert_eq!(shell_quote(""), "''");
+        assert_eq!(shell_quote("a b"), "'a b'");
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+    }
+}
@@ -0,0 +1,213 @@
+// SPDX-FileCopyrightText: 2025 Madeline Baggins <declanbaggins@gmail.com>
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+use std::path::Path;
+
+use maddi_git_manager::{
+    cfg,
+    query::{Field, Searchable},
+    search::{xml_escape, xml_unescape},
+};
+use maddi_xml::{
+    Content, Element, FromElement, Parser, Result,
+};
+
+/// A repository that can be cloned, either found by
+/// searching a remote or listed in the config as `<git>`.
+#[derive(Debug, Clone)]
+pub struct Repository {
+    pub alias: String,
+    pub remote: Option<String>,
+    pub path: String,
+    pub tags: Vec<String>,
+    pub symlinks: Vec<String>,
+    pub description: Option<String>,
+    pub homepage: Option<String>,
+    pub owner: Option<String>,
+    pub license: Option<String>,
+    /// Whether it came from an out of date cache
+    pub stale: bool,
+}
+
+impl Repository {
+    /// Parses the `<results>` document printed by
+    /// `git-manager search`.
+    pub fn parse_results(
+        source: &str,
+    ) -> std::result::Result<Vec<Self>, String> {
+        let mut parser =
+            Parser::new(Path::new("<results>"), source);
+        let expected = "expected 'results' element";
+        let results = match parser
+            .parse::<Option<Result<Content>>>()
+        {
+            Some(Ok(Content::Element(results)))
+                if results.name == "results" =>
+            {
+                results
+            }
+            Some(Err(err)) => return Err(err.to_string()),
+            _ => return Err(expected.into()),
+        };
+        results
+            .children("git")
+            .collect::<Result<_>>()
+            .map_err(|err| err.to_string())
+    }
+    /// Renders repositories as a `<results>` document that
+    /// `parse_results` can read back.
+    pub fn render_results(repositories: &[Self]) -> String {
+        let mut rendered = String::from("<results>\n");
+        for repository in repositories {
+            rendered.push_str(&repository.xml());
+        }
+        rendered.push_str("</results>\n");
+        rendered
+    }
+    /// Renders the repository as a `<git>` element.
+    fn xml(&self) -> String {
+        let element = |name: &str, value: &str| {
+            format!(
+                "    <{name}>{}</{name}>\n",
+                xml_escape(value)
+            )
+        };
+        let mut rendered = String::from("  <git>\n");
+        rendered.push_str(&element("alias", &self.alias));
+        if let Some(remote) = &self.remote {
+            rendered.push_str(&element("remote", remote));
+        }
+        rendered.push_str(&element("path", &self.path));
+        for tag in &self.tags {
+            rendered.push_str(&element("tag", tag));
+        }
+        for link in &self.symlinks {
+            rendered.push_str(&element("symlink", link));
+        }
+        let metadata = [
+            ("description", &self.description),
+            ("homepage", &self.homepage),
+            ("owner", &self.owner),
+            ("license", &self.license),
+        ];
+        for (name, value) in metadata {
+            if let Some(value) = value {
+                rendered.push_str(&element(name, value));
+            }
+        }
+        rendered.push_str("  </git>\n");
+        rendered
+    }
+    /// Every repository in a git-manager config, which
+    /// can be cloned from `remote`.
+    pub fn from_config(
+        config: &cfg::Config,
+        remote: Option<&str>,
+    ) -> Vec<Self> {
+        config
+            .repositories
+            .iter()
+            .map(|repository| {
+                let metadata = repository.metadata();
+                let name = repository.name();
+                Self {
+                    alias: name.into(),
+                    remote: remote.map(Into::into),
+                    path: config
+                        .store
+                        .join(name)
+                        .display()
+                        .to_string(),
+                    tags: repository.tags().to_vec(),
+                    symlinks: repository
+                        .symlinks(&config.symlinks)
+                        .map(|link| {
+                            link.display().to_string()
+                        })
+                        .collect(),
+                    description: metadata
+                        .description
+                        .clone(),
+                    homepage: metadata.homepage.clone(),
+                    owner: metadata.owner.clone(),
+                    license: metadata.license.clone(),
+                    stale: false,
+                }
+            })
+            .collect()
+    }
+    /// Where `git clone` can find the repository.
+    pub fn url(&self) -> String {
+        match &self.remote {
+            Some(remote) => {
+                format!("{remote}:{}", self.path)
+            }
+            None => self.path.clone(),
+        }
+    }
+}
+
+impl<'a, 'b> FromElement<'a, 'b> for Repository {
+    fn from_element(
+        element: &'b Element<'a>,
+    ) -> Result<'a, Self> {
+        let text =
+            |value: String| xml_unescape(value.trim());
+        let optional = |name| {
+            Ok(element
+                .optional_child::<String>(name)?
+                .map(text))
+        };
+        let all = |name| {
+            element
+                .children::<String>(name)
+                .map(|value| value.map(text))
+                .collect::<Result<_>>()
+        };
+        Ok(Self {
+            alias: text(element.child("alias")?),
+            remote: optional("remote")?,
+            path: text(element.child("path")?),
+            tags: all("tag")?,
+            symlinks: all("symlink")?,
+            description: optional("description")?,
+            homepage: optional("homepage")?,
+            owner: optional("owner")?,
+            license: optional("license")?,
+            stale: false,
+        })
+    }
+}
+
+impl Searchable for Repository {
+    fn values(&self, field: Field) -> Vec<String> {
+        let optional = |value: &Option<String>| {
+            value.iter().cloned().collect()
+        };
+        match field {
+            Field::Name => vec![self.alias.clone()],
+            Field::Tag => self.tags.clone(),
+            Field::Symlink => self.symlinks.clone(),
+            Field::Description => {
+                optional(&self.description)
+            }
+            Field::Homepage => optional(&self.homepage),
+            Field::Owner => optional(&self.owner),
+            Field::License => optional(&self.license),
+        }
+    }
+}
+
+impl std::fmt::Display for Repository {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        write!(f, "{}\t{}", self.alias, self.url())?;
+        if self.stale {
+            write!(f, "\t(stale)")?;
+        }
+        Ok(())
+    }
+}